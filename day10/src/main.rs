@@ -1,6 +1,31 @@
 use std::collections::HashSet;
 use std::env;
-use std::fs::read_to_string;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::process::exit;
+
+type Pos = (usize, usize);
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Dir {
+    N,
+    E,
+    S,
+    W,
+}
+
+impl Dir {
+    const ALL: [Dir; 4] = [Dir::N, Dir::E, Dir::S, Dir::W];
+
+    fn opposite(self) -> Dir {
+        match self {
+            Dir::N => Dir::S,
+            Dir::E => Dir::W,
+            Dir::S => Dir::N,
+            Dir::W => Dir::E,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 enum Pipe {
@@ -14,18 +39,112 @@ enum Pipe {
     Ground,
 }
 
+impl Pipe {
+    // The two directions a pipe connects to, or None for the start and ground tiles.
+    fn dirs(self) -> Option<[Dir; 2]> {
+        match self {
+            Pipe::NS => Some([Dir::N, Dir::S]),
+            Pipe::EW => Some([Dir::E, Dir::W]),
+            Pipe::NE => Some([Dir::N, Dir::E]),
+            Pipe::NW => Some([Dir::N, Dir::W]),
+            Pipe::SW => Some([Dir::S, Dir::W]),
+            Pipe::SE => Some([Dir::S, Dir::E]),
+            Pipe::Start | Pipe::Ground => None,
+        }
+    }
+
+    fn has(self, dir: Dir) -> bool {
+        self.dirs().is_some_and(|dirs| dirs.contains(&dir))
+    }
+
+    fn glyph(self, heavy: bool) -> char {
+        match (self, heavy) {
+            (Pipe::NS, false) => '│',
+            (Pipe::EW, false) => '─',
+            (Pipe::NE, false) => '└',
+            (Pipe::NW, false) => '┘',
+            (Pipe::SW, false) => '┐',
+            (Pipe::SE, false) => '┌',
+            (Pipe::NS, true) => '┃',
+            (Pipe::EW, true) => '━',
+            (Pipe::NE, true) => '┗',
+            (Pipe::NW, true) => '┛',
+            (Pipe::SW, true) => '┓',
+            (Pipe::SE, true) => '┏',
+            (Pipe::Start, _) => 'S',
+            (Pipe::Ground, _) => '.',
+        }
+    }
+
+    fn from_dirs(a: Dir, b: Dir) -> Option<Pipe> {
+        [Pipe::NS, Pipe::EW, Pipe::NE, Pipe::NW, Pipe::SW, Pipe::SE]
+            .into_iter()
+            .find(|p| a != b && p.has(a) && p.has(b))
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Render {
+    Plain,
+    Ansi,
+    Svg,
+}
+
+#[derive(Debug, PartialEq)]
+enum MazeError {
+    UnknownTile { pos: Pos, c: char },
+    NoStart,
+    MultipleStarts(Vec<Pos>),
+    // The pipe at `pos` leads towards `dir`, but nothing there connects back.
+    DeadEnd { pos: Pos, dir: Dir },
+    // The start tile has fewer than two neighbours connecting into it.
+    UnconnectedStart { pos: Pos, dirs: Vec<Dir> },
+    // The start tile has more than two neighbours connecting into it.
+    Branching { pos: Pos, dirs: Vec<Dir> },
+    NotAPipe(Pos),
+}
+
+impl fmt::Display for MazeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MazeError::UnknownTile { pos, c } => write!(f, "unknown tile {:?} at {:?}", c, pos),
+            MazeError::NoStart => write!(f, "no start tile 'S' found"),
+            MazeError::MultipleStarts(v) => write!(f, "multiple start tiles found at {:?}", v),
+            MazeError::DeadEnd { pos, dir } => {
+                write!(
+                    f,
+                    "dead end: pipe at {:?} leads {:?} but nothing connects back",
+                    pos, dir
+                )
+            }
+            MazeError::UnconnectedStart { pos, dirs } => {
+                write!(f, "start tile at {:?} only connects to {:?}", pos, dirs)
+            }
+            MazeError::Branching { pos, dirs } => {
+                write!(
+                    f,
+                    "branching: start tile at {:?} connects to {:?}",
+                    pos, dirs
+                )
+            }
+            MazeError::NotAPipe(pos) => write!(f, "tile at {:?} is not a pipe", pos),
+        }
+    }
+}
+
 struct Area {
     area: Vec<Vec<Pipe>>,
-    main_loop_vec: Vec<(usize, usize)>,
-    main_loop_set: HashSet<(usize, usize)>,
+    start: Pos,
+    main_loop_vec: Vec<Pos>,
+    main_loop_set: HashSet<Pos>,
 }
 
 impl Area {
-    fn new(input: &str) -> Self {
+    fn new(input: &str) -> Result<Self, MazeError> {
         let mut vvs: Vec<Vec<Pipe>> = Vec::new();
-        input.lines().for_each(|line| {
+        for (x, line) in input.lines().enumerate() {
             let mut vs: Vec<Pipe> = Vec::new();
-            line.chars().for_each(|c| {
+            for (y, c) in line.chars().enumerate() {
                 vs.push(match c {
                     '|' => Pipe::NS,
                     '-' => Pipe::EW,
@@ -35,231 +154,391 @@ impl Area {
                     'F' => Pipe::SE,
                     '.' => Pipe::Ground,
                     'S' => Pipe::Start,
-                    _ => panic!("unreachable"),
+                    _ => return Err(MazeError::UnknownTile { pos: (x, y), c }),
                 });
-            });
+            }
             vvs.push(vs);
-        });
+        }
         let mut area = Area {
             area: vvs,
+            start: (0, 0),
             main_loop_vec: Vec::new(),
             main_loop_set: HashSet::new(),
         };
-        let main_loop = area.build_loop();
-        area.main_loop_vec = main_loop;
-        let loop_set: HashSet<(usize, usize)> = area.main_loop_vec.clone().into_iter().collect();
-        area.main_loop_set = loop_set;
-
-        let start = area.main_loop_vec.first().unwrap();
-        let before_start = area.main_loop_vec.last().unwrap();
-        let after_start = area.main_loop_vec.get(1).unwrap();
-
-        let gap = (
-            after_start.0 as i64 - before_start.0 as i64,
-            after_start.1 as i64 - before_start.1 as i64,
-        );
+        area.start = area.start_pos()?;
+        let start_pipe = area.infer_start_pipe()?;
+        area.area[area.start.0][area.start.1] = start_pipe;
+        area.main_loop_vec = area.trace_loop(area.start)?;
+        area.main_loop_set = area.main_loop_vec.iter().copied().collect();
+        Ok(area)
+    }
 
-        let start_pipe = match gap {
-            (2, 0) | (-2, 0) => Pipe::NS,
-            (0, 2) | (0, -2) => Pipe::EW,
-            (1, 1) => {
-                if start.0 == after_start.0 {
-                    Pipe::NE
-                } else {
-                    Pipe::SW
-                }
-            }
-            (1, -1) => {
-                if start.0 == after_start.0 {
-                    Pipe::NW
-                } else {
-                    Pipe::SE
-                }
-            }
-            (-1, 1) => {
-                if start.0 == after_start.0 {
-                    Pipe::SE
-                } else {
-                    Pipe::NW
-                }
-            }
-            (-1, -1) => {
-                if start.0 == after_start.0 {
-                    Pipe::SW
-                } else {
-                    Pipe::NE
-                }
-            }
-            _ => panic!("unreachable"),
+    fn start_pos(&self) -> Result<Pos, MazeError> {
+        let starts: Vec<Pos> = self
+            .area
+            .iter()
+            .enumerate()
+            .flat_map(|(x, vs)| {
+                vs.iter()
+                    .enumerate()
+                    .filter(|(_, v)| **v == Pipe::Start)
+                    .map(move |(y, _)| (x, y))
+            })
+            .collect();
+        match starts.len() {
+            0 => Err(MazeError::NoStart),
+            1 => Ok(starts[0]),
+            _ => Err(MazeError::MultipleStarts(starts)),
+        }
+    }
+
+    fn get(&self, pos: Pos) -> Pipe {
+        self.area[pos.0][pos.1]
+    }
+
+    fn neighbour(&self, pos: Pos, dir: Dir) -> Option<Pos> {
+        let (x, y) = pos;
+        let n = match dir {
+            Dir::N => (x.checked_sub(1)?, y),
+            Dir::E => (x, y + 1),
+            Dir::S => (x + 1, y),
+            Dir::W => (x, y.checked_sub(1)?),
         };
-        area.area[start.0][start.1] = start_pipe;
-        area
+        self.area.get(n.0)?.get(n.1)?;
+        Some(n)
     }
 
-    fn start_pipe(&self) -> (usize, usize) {
-        for (x, vs) in self.area.iter().enumerate() {
-            for (y, v) in vs.iter().enumerate() {
-                if *v == Pipe::Start {
-                    return (x, y);
-                }
-            }
+    // The start pipe is whatever shape joins the two neighbours that connect back into it.
+    fn infer_start_pipe(&self) -> Result<Pipe, MazeError> {
+        let pos = self.start;
+        let dirs: Vec<Dir> = Dir::ALL
+            .into_iter()
+            .filter(|dir| {
+                self.neighbour(pos, *dir)
+                    .is_some_and(|n| self.get(n).has(dir.opposite()))
+            })
+            .collect();
+        match dirs.len() {
+            0 | 1 => Err(MazeError::UnconnectedStart { pos, dirs }),
+            2 => Ok(Pipe::from_dirs(dirs[0], dirs[1]).unwrap()),
+            _ => Err(MazeError::Branching { pos, dirs }),
         }
-        panic!("unreachable");
     }
 
-    fn build_loop(self: &Self) -> Vec<(usize, usize)> {
-        let mut vec: Vec<(usize, usize)> = Vec::new();
-        let start = self.start_pipe();
-        let (x, y) = start;
-        vec.push(start);
+    // Follows the pipe at `from` towards `dir`, checking that the tile there connects back.
+    fn step(&self, from: Pos, dir: Dir) -> Result<(Pos, Dir), MazeError> {
+        let dead_end = MazeError::DeadEnd { pos: from, dir };
+        let to = self.neighbour(from, dir).ok_or(dead_end)?;
+        match self.get(to).dirs() {
+            Some([a, b]) if a == dir.opposite() => Ok((to, b)),
+            Some([a, b]) if b == dir.opposite() => Ok((to, a)),
+            _ => Err(MazeError::DeadEnd { pos: from, dir }),
+        }
+    }
+
+    // Walks the loop through `start`, returning its tiles in order beginning with `start`.
+    fn trace_loop(&self, start: Pos) -> Result<Vec<Pos>, MazeError> {
+        let mut vec = vec![start];
+        let [mut dir, _] = self.get(start).dirs().ok_or(MazeError::NotAPipe(start))?;
+        let mut pos = start;
         loop {
-            if let Some(vs) = self.area.get(x) {
-                if y > 0 {
-                    if let Some(v) = vs.get(y - 1) {
-                        if *v == Pipe::EW || *v == Pipe::NE || *v == Pipe::SE {
-                            vec.push((x, y - 1));
-                            break;
-                        }
-                    }
+            (pos, dir) = self.step(pos, dir)?;
+            if pos == start {
+                break Ok(vec);
+            }
+            vec.push(pos);
+        }
+    }
+
+    // Every closed loop in the map, the main loop included. Pipes that end up in a dead end are
+    // skipped.
+    fn loops(&self) -> Vec<Vec<Pos>> {
+        let mut seen: HashSet<Pos> = HashSet::new();
+        let mut loops = Vec::new();
+        for (x, vs) in self.area.iter().enumerate() {
+            for y in 0..vs.len() {
+                if seen.contains(&(x, y)) || self.get((x, y)).dirs().is_none() {
+                    continue;
                 }
-                if let Some(v) = vs.get(y + 1) {
-                    if *v == Pipe::EW || *v == Pipe::NW || *v == Pipe::SW {
-                        vec.push((x, y + 1));
-                        break;
-                    }
+                if let Ok(l) = self.trace_loop((x, y)) {
+                    seen.extend(l.iter().copied());
+                    loops.push(l);
+                } else {
+                    seen.insert((x, y));
                 }
             }
-            if x > 0 {
-                if let Some(vs) = self.area.get(x - 1) {
-                    if let Some(v) = vs.get(y) {
-                        if *v == Pipe::NS || *v == Pipe::SW || *v == Pipe::SE {
-                            vec.push((x - 1, y));
-                            break;
+        }
+        loops
+    }
+
+    // Counts enclosed tiles row by row, flipping the inside state whenever the loop is crossed.
+    fn scanline_enclosed(&self) -> usize {
+        let mut cnt = 0;
+        self.area.iter().enumerate().for_each(|(x, line)| {
+            let mut in_loop = false;
+            let mut half_boundary: Option<Pipe> = None;
+            line.iter().enumerate().for_each(|(y, p)| {
+                if self.main_loop_set.contains(&(x, y)) {
+                    match *p {
+                        Pipe::NS => {
+                            in_loop = !in_loop;
+                            half_boundary = None;
+                        }
+                        Pipe::SE => {
+                            half_boundary = Some(Pipe::SE);
                         }
+                        Pipe::NE => {
+                            half_boundary = Some(Pipe::NE);
+                        }
+                        Pipe::SW => {
+                            let hb = half_boundary.take().unwrap();
+                            if hb == Pipe::NE {
+                                in_loop = !in_loop;
+                            }
+                        }
+                        Pipe::NW => {
+                            let hb = half_boundary.take().unwrap();
+                            if hb == Pipe::SE {
+                                in_loop = !in_loop;
+                            }
+                        }
+                        _ => {}
                     }
+                    return;
                 }
-            }
-            if let Some(vs) = self.area.get(x + 1) {
-                if let Some(v) = vs.get(y) {
-                    if *v == Pipe::NS || *v == Pipe::NW || *v == Pipe::NE {
-                        vec.push((x + 1, y));
-                        break;
-                    }
+                if in_loop {
+                    cnt += 1;
                 }
-            }
-        }
-        assert!(vec.len() == 2);
+            })
+        });
+        cnt
+    }
 
-        loop {
-            if let Some(pos) = self.next(&vec) {
-                vec.push(pos);
-            } else {
-                break vec;
-            }
-        }
+    // Counts enclosed tiles from the loop's vertices: the shoelace formula gives the area of the
+    // polygon through the tile centres, and Pick's theorem turns that into interior lattice points.
+    fn pick_enclosed(&self) -> usize {
+        let n = self.main_loop_vec.len();
+        let twice_area: i64 = (0..n)
+            .map(|i| {
+                let (x1, y1) = self.main_loop_vec[i];
+                let (x2, y2) = self.main_loop_vec[(i + 1) % n];
+                x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64
+            })
+            .sum();
+        (twice_area.unsigned_abs() as usize + 2 - n) / 2
     }
 
-    fn next(self: &Self, vec: &Vec<(usize, usize)>) -> Option<(usize, usize)> {
-        let (x_ll, y_ll) = vec.get(vec.len() - 2).unwrap().clone();
-        let (x, y) = vec.last().unwrap().clone();
-        let mut pos = (0, 0);
-        match self.area.get(x).unwrap().get(y).unwrap() {
-            Pipe::NS => {
-                if (x_ll, y_ll) != (x + 1, y) {
-                    pos = (x + 1, y);
-                } else if (x_ll, y_ll) != (x - 1, y) {
-                    pos = (x - 1, y);
+    // Flood fills from outside the map on a 2x upscaled grid, where tile (x, y) sits at
+    // (2x + 1, 2y + 1) and the cells in between are only walls if the main loop passes through
+    // them. That lets the fill squeeze between adjacent pipes. Returns the enclosed tiles.
+    fn flood_enclosed(&self) -> Vec<Pos> {
+        let h = self.area.len() * 2 + 1;
+        let w = self.area.iter().map(|vs| vs.len()).max().unwrap_or(0) * 2 + 1;
+        let mut wall = vec![vec![false; w]; h];
+        for &(x, y) in &self.main_loop_vec {
+            let (ux, uy) = (2 * x + 1, 2 * y + 1);
+            wall[ux][uy] = true;
+            for dir in self.get((x, y)).dirs().unwrap() {
+                match dir {
+                    Dir::N => wall[ux - 1][uy] = true,
+                    Dir::E => wall[ux][uy + 1] = true,
+                    Dir::S => wall[ux + 1][uy] = true,
+                    Dir::W => wall[ux][uy - 1] = true,
                 }
             }
-            Pipe::EW => {
-                if (x_ll, y_ll) != (x, y + 1) {
-                    pos = (x, y + 1);
-                } else if (x_ll, y_ll) != (x, y - 1) {
-                    pos = (x, y - 1);
+        }
+
+        let mut outside = vec![vec![false; w]; h];
+        let mut stack: Vec<Pos> = vec![(0, 0)];
+        outside[0][0] = true;
+        while let Some((x, y)) = stack.pop() {
+            let next = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in next {
+                if nx < h && ny < w && !wall[nx][ny] && !outside[nx][ny] {
+                    outside[nx][ny] = true;
+                    stack.push((nx, ny));
                 }
             }
-            Pipe::NE => {
-                if (x_ll, y_ll) != (x, y + 1) {
-                    pos = (x, y + 1);
-                } else if (x_ll, y_ll) != (x - 1, y) {
-                    pos = (x - 1, y);
+        }
+
+        let mut enclosed = Vec::new();
+        for (x, vs) in self.area.iter().enumerate() {
+            for y in 0..vs.len() {
+                if !self.main_loop_set.contains(&(x, y)) && !outside[2 * x + 1][2 * y + 1] {
+                    enclosed.push((x, y));
                 }
             }
-            Pipe::NW => {
-                if (x_ll, y_ll) != (x, y - 1) {
-                    pos = (x, y - 1);
-                } else if (x_ll, y_ll) != (x - 1, y) {
-                    pos = (x - 1, y);
+        }
+        enclosed
+    }
+
+    // Draws the maze with the main loop highlighted and every other tile, junk pipes included,
+    // marked "I" (enclosed) or "O" (outside), as in the puzzle's illustrations. Plain text uses
+    // heavy box-drawing glyphs for the loop and "S" for the start, ANSI uses bold colours.
+    fn render(&self, format: Render) -> String {
+        if format == Render::Svg {
+            return self.render_svg();
+        }
+        let enclosed: HashSet<Pos> = self.flood_enclosed().into_iter().collect();
+        let mut out = String::new();
+        for (x, vs) in self.area.iter().enumerate() {
+            for (y, p) in vs.iter().enumerate() {
+                let pos = (x, y);
+                let on_loop = self.main_loop_set.contains(&pos);
+                let inside = enclosed.contains(&pos);
+                match format {
+                    Render::Ansi if pos == self.start => {
+                        out.push_str(&format!("\x1b[1;31m{}\x1b[0m", p.glyph(false)))
+                    }
+                    Render::Ansi if on_loop => {
+                        out.push_str(&format!("\x1b[1;32m{}\x1b[0m", p.glyph(false)))
+                    }
+                    Render::Ansi if inside => out.push_str("\x1b[1;33mI\x1b[0m"),
+                    Render::Ansi => out.push_str("\x1b[2mO\x1b[0m"),
+                    _ if pos == self.start => out.push('S'),
+                    _ if on_loop => out.push(p.glyph(true)),
+                    _ if inside => out.push('I'),
+                    _ => out.push('O'),
                 }
             }
-            Pipe::SW => {
-                if (x_ll, y_ll) != (x, y - 1) {
-                    pos = (x, y - 1);
-                } else if (x_ll, y_ll) != (x + 1, y) {
-                    pos = (x + 1, y);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn render_svg(&self) -> String {
+        const CELL: usize = 10;
+        let h = self.area.len();
+        let w = self.area.iter().map(|vs| vs.len()).max().unwrap_or(0);
+        let centre = |(x, y): Pos| (y * CELL + CELL / 2, x * CELL + CELL / 2);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+            w * CELL,
+            h * CELL
+        );
+        out.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+        for (x, y) in self.flood_enclosed() {
+            out.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"gold\"/>\n",
+                y * CELL,
+                x * CELL,
+                CELL,
+                CELL
+            ));
+        }
+        // Pipes that are not part of the main loop are drawn as thin grey segments.
+        for (x, vs) in self.area.iter().enumerate() {
+            for (y, p) in vs.iter().enumerate() {
+                if self.main_loop_set.contains(&(x, y)) {
+                    continue;
                 }
-            }
-            Pipe::SE => {
-                if (x_ll, y_ll) != (x, y + 1) {
-                    pos = (x, y + 1);
-                } else if (x_ll, y_ll) != (x + 1, y) {
-                    pos = (x + 1, y);
+                let (cx, cy) = centre((x, y));
+                for dir in p.dirs().into_iter().flatten() {
+                    let (dx, dy) = match dir {
+                        Dir::N => (cx, cy - CELL / 2),
+                        Dir::E => (cx + CELL / 2, cy),
+                        Dir::S => (cx, cy + CELL / 2),
+                        Dir::W => (cx - CELL / 2, cy),
+                    };
+                    out.push_str(&format!(
+                        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"grey\"/>\n",
+                        cx, cy, dx, dy
+                    ));
                 }
             }
-            _ => panic!("unreachable"),
-        };
-        if pos == vec.first().unwrap().clone() {
-            return None;
         }
-        Some(pos)
+        let points: Vec<String> = self
+            .main_loop_vec
+            .iter()
+            .map(|pos| {
+                let (cx, cy) = centre(*pos);
+                format!("{},{}", cx, cy)
+            })
+            .collect();
+        out.push_str(&format!(
+            "<polygon points=\"{}\" fill=\"none\" stroke=\"green\" stroke-width=\"3\"/>\n",
+            points.join(" ")
+        ));
+        let (sx, sy) = centre(self.start);
+        out.push_str(&format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"/>\n",
+            sx,
+            sy,
+            CELL / 3
+        ));
+        out.push_str("</svg>\n");
+        out
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        println!("./exe <file> [--loops] [--check] [--enclosed] [--render plain|ansi|svg [--out <file>]]");
+        exit(1);
     }
-    let area = Area::new(&read_to_string(&args[1]).unwrap());
+    let flags = &args[2..];
+    let has_flag = |flag: &str| flags.iter().any(|a| a == flag);
+    let area = match Area::new(&read_to_string(&args[1]).unwrap()) {
+        Ok(area) => area,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
     println!("{}", area.main_loop_vec.len() / 2);
 
-    let mut cnt = 0;
-    area.area.iter().enumerate().for_each(|(x, line)| {
-        let mut in_loop = false;
-        let mut half_boundary: Option<Pipe> = None;
-        line.iter().enumerate().for_each(|(y, p)| {
-            if area.main_loop_set.contains(&(x, y)) {
-                match *p {
-                    Pipe::NS => {
-                        in_loop = !in_loop;
-                        half_boundary = None;
-                    }
-                    Pipe::SE => {
-                        half_boundary = Some(Pipe::SE);
-                    }
-                    Pipe::NE => {
-                        half_boundary = Some(Pipe::NE);
-                    }
-                    Pipe::SW => {
-                        let hb = half_boundary.take().unwrap();
-                        if hb == Pipe::NE {
-                            in_loop = !in_loop;
-                        }
-                    }
-                    Pipe::NW => {
-                        let hb = half_boundary.take().unwrap();
-                        if hb == Pipe::SE {
-                            in_loop = !in_loop;
-                        }
-                    }
-                    _ => {}
-                }
-                return;
-            }
-            if in_loop {
-                cnt += 1;
-            }
-        })
-    });
+    let cnt = area.scanline_enclosed();
     println!("{}", cnt);
+
+    if has_flag("--check") {
+        let pick = area.pick_enclosed();
+        let flood = area.flood_enclosed().len();
+        println!(
+            "scanline: {}, shoelace+pick: {}, flood fill: {}",
+            cnt, pick, flood
+        );
+        if pick != cnt || flood != cnt {
+            println!("enclosed tile counts disagree");
+            exit(1);
+        }
+    }
+
+    if has_flag("--enclosed") {
+        for pos in area.flood_enclosed() {
+            println!("{:?}", pos);
+        }
+    }
+
+    if let Some(i) = flags.iter().position(|a| a == "--render") {
+        let format = match flags.get(i + 1).map(|s| s.as_str()) {
+            Some("plain") => Render::Plain,
+            Some("ansi") => Render::Ansi,
+            Some("svg") => Render::Svg,
+            _ => {
+                println!("--render expects one of plain, ansi or svg");
+                exit(1);
+            }
+        };
+        match flags.iter().position(|a| a == "--out") {
+            Some(j) => write(&flags[j + 1], area.render(format)).unwrap(),
+            None => print!("{}", area.render(format)),
+        }
+    }
+
+    if has_flag("--loops") {
+        for l in area.loops() {
+            let tag = if area.main_loop_set.contains(&l[0]) {
+                " (main)"
+            } else {
+                ""
+            };
+            println!("loop of {} tiles starting at {:?}{}", l.len(), l[0], tag);
+        }
+    }
 }
@@ -1,83 +1,332 @@
-use std::cmp::{max, min};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::read_to_string;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
-    }
-    let content = read_to_string(&args[1]).unwrap();
+type Point = (usize, usize);
+
+struct Universe {
+    galaxies: Vec<Point>,
+    empty_row: HashSet<usize>,
+    empty_col: HashSet<usize>,
+    height: usize,
+    width: usize,
+}
+
+impl Universe {
+    fn new(content: String) -> Self {
+        let raw_input: Vec<Vec<char>> = parse_input(content);
+
+        let mut empty_row: HashSet<usize> = HashSet::new();
+        let mut empty_col: HashSet<usize> = HashSet::new();
+        raw_input.iter().enumerate().for_each(|(i, line)| {
+            if line.iter().all(|c| *c == '.') {
+                empty_row.insert(i);
+            }
+        });
+        for i in 0..raw_input[0].len() {
+            if raw_input.iter().map(|line| line[i]).all(|c| c == '.') {
+                empty_col.insert(i);
+            }
+        }
 
-    let raw_input: Vec<Vec<char>> = parse_input(content);
+        let mut galaxies: Vec<Point> = Vec::new();
+        raw_input.iter().enumerate().for_each(|(x, line)| {
+            line.iter().enumerate().for_each(|(y, c)| {
+                if *c == '#' {
+                    galaxies.push((x, y));
+                }
+            })
+        });
 
-    let mut empty_row: HashSet<usize> = HashSet::new();
-    let mut empty_col: HashSet<usize> = HashSet::new();
-    raw_input.iter().enumerate().for_each(|(i, line)| {
-        if line.iter().all(|c| *c == '.') {
-            empty_row.insert(i);
+        Universe {
+            galaxies,
+            empty_row,
+            empty_col,
+            height: raw_input.len(),
+            width: raw_input[0].len(),
         }
-    });
-    for i in 0..raw_input[0].len() {
-        if raw_input.iter().map(|line| line[i]).all(|c| c == '.') {
-            empty_col.insert(i);
+    }
+
+    // Galaxy coordinates after every empty row and column has grown to `factor` rows/columns.
+    fn expanded(&self, factor: usize) -> Vec<Point> {
+        self.expanded_weighted(&Weights::uniform(factor))
+    }
+
+    // Galaxy coordinates after every empty row and column has grown to its own weight.
+    fn expanded_weighted(&self, weights: &Weights) -> Vec<Point> {
+        let rows = prefix_growth(&self.empty_row, self.height, |i| weights.row(i));
+        let cols = prefix_growth(&self.empty_col, self.width, |i| weights.col(i));
+        self.galaxies
+            .iter()
+            .map(|(x, y)| (x + rows[*x], y + cols[*y]))
+            .collect()
+    }
+}
+
+// How many rows/columns each empty line expands into. Lines without an explicit weight use the
+// default.
+struct Weights {
+    default: usize,
+    rows: HashMap<usize, usize>,
+    cols: HashMap<usize, usize>,
+}
+
+impl Weights {
+    fn uniform(factor: usize) -> Self {
+        Weights {
+            default: factor,
+            rows: HashMap::new(),
+            cols: HashMap::new(),
         }
     }
 
-    // part1
-    let mut galaxies: Vec<(usize, usize)> = Vec::new();
-    raw_input.iter().enumerate().for_each(|(x, line)| {
-        line.iter().enumerate().for_each(|(y, c)| {
-            if *c == '#' {
-                galaxies.push((x, y));
+    // Reads a config of `row <index> <weight>`, `col <index> <weight>` and `default <weight>`
+    // lines. Blank lines and lines starting with `#` are ignored.
+    fn parse(content: &str, default: usize) -> Self {
+        let mut weights = Weights::uniform(default);
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
             }
-        })
-    });
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["default", w] => weights.default = parse_weight(w),
+                ["row", i, w] => {
+                    weights.rows.insert(i.parse().unwrap(), parse_weight(w));
+                }
+                ["col", i, w] => {
+                    weights.cols.insert(i.parse().unwrap(), parse_weight(w));
+                }
+                _ => panic!("invalid weight line: {}", line),
+            }
+        }
+        weights
+    }
 
-    let mut sum1 = 0;
-    let mut sum2 = 0;
-    for i in 0..galaxies.len() {
-        for j in i + 1..galaxies.len() {
-            sum1 += distance(galaxies[i], galaxies[j], &empty_row, &empty_col, 2);
-            sum2 += distance(galaxies[i], galaxies[j], &empty_row, &empty_col, 1000000);
+    fn row(&self, i: usize) -> usize {
+        *self.rows.get(&i).unwrap_or(&self.default)
+    }
+
+    fn col(&self, i: usize) -> usize {
+        *self.cols.get(&i).unwrap_or(&self.default)
+    }
+}
+
+// An empty line always stays at least one line wide.
+fn parse_weight(s: &str) -> usize {
+    match s.parse() {
+        Ok(w) if w >= 1 => w,
+        _ => panic!("weight must be a whole number of at least 1, got {}", s),
+    }
+}
+
+// prefix[i] is how much the empty lines before line i have grown in total.
+fn prefix_growth(
+    empty: &HashSet<usize>,
+    len: usize,
+    weight: impl Fn(usize) -> usize,
+) -> Vec<usize> {
+    let mut prefix = vec![0; len + 1];
+    for i in 0..len {
+        prefix[i + 1] = prefix[i] + if empty.contains(&i) { weight(i) - 1 } else { 0 };
+    }
+    prefix
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Metric {
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
+impl Metric {
+    fn distance(self, i: Point, j: Point) -> f64 {
+        let dx = i.0.abs_diff(j.0);
+        let dy = i.1.abs_diff(j.1);
+        match self {
+            Metric::Manhattan => (dx + dy) as f64,
+            Metric::Chebyshev => dx.max(dy) as f64,
+            Metric::Euclidean => ((dx * dx + dy * dy) as f64).sqrt(),
         }
     }
 
-    println!("{}", sum1);
-    println!("{}", sum2);
+    // Sum over all pairs. Chebyshev distance is half the Manhattan distance in coordinates rotated
+    // by 45 degrees, so both use the sorting trick; Euclidean has to look at every pair.
+    fn sum(self, points: &[Point]) -> f64 {
+        match self {
+            Metric::Manhattan => sum_distances(points) as f64,
+            Metric::Chebyshev => {
+                // Shift by the maximum y so the rotated coordinates stay unsigned.
+                let max_y = points.iter().map(|p| p.1).max().unwrap_or(0);
+                let rotated: Vec<Point> =
+                    points.iter().map(|(x, y)| (x + y, x + max_y - y)).collect();
+                sum_distances(&rotated) as f64 / 2.0
+            }
+            Metric::Euclidean => pairs(points.len())
+                .map(|(i, j)| self.distance(points[i], points[j]))
+                .sum(),
+        }
+    }
+}
+
+fn pairs(n: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..n).flat_map(move |i| (i + 1..n).map(move |j| (i, j)))
 }
 
-fn distance(
-    i: (usize, usize),
-    j: (usize, usize),
-    empty_row: &HashSet<usize>,
-    empty_col: &HashSet<usize>,
-    factor: usize,
-) -> usize {
-    let row_exp = (min(i.0, j.0)..max(i.0, j.0))
-        .filter(|idx| empty_row.contains(idx))
-        .count();
+// The k closest and the k farthest pairs, each as (i, j, distance).
+#[allow(clippy::type_complexity)]
+fn extreme_pairs(
+    points: &[Point],
+    metric: Metric,
+    k: usize,
+) -> (Vec<(usize, usize, f64)>, Vec<(usize, usize, f64)>) {
+    let mut all: Vec<(usize, usize, f64)> = pairs(points.len())
+        .map(|(i, j)| (i, j, metric.distance(points[i], points[j])))
+        .collect();
+    all.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let closest = all.iter().take(k).copied().collect();
+    let farthest = all.iter().rev().take(k).copied().collect();
+    (closest, farthest)
+}
 
-    let col_exp = (min(i.1, j.1)..max(i.1, j.1))
-        .filter(|idx| empty_col.contains(idx))
-        .count();
+// Sum of the Manhattan distances between all pairs. Each axis is independent, and once sorted the
+// k-th coordinate is larger than the k coordinates before it, so it contributes k * c minus their
+// sum.
+fn sum_distances(points: &[Point]) -> usize {
+    let axis_sum = |mut cs: Vec<usize>| {
+        cs.sort_unstable();
+        let mut seen = 0;
+        let mut sum = 0;
+        for (k, c) in cs.into_iter().enumerate() {
+            sum += k * c - seen;
+            seen += c;
+        }
+        sum
+    };
+    axis_sum(points.iter().map(|p| p.0).collect()) + axis_sum(points.iter().map(|p| p.1).collect())
+}
 
-    let x_diff;
-    if i.0 > j.0 {
-        x_diff = i.0 - j.0;
-    } else {
-        x_diff = j.0 - i.0;
+// The closest other point to points[i], as (index, distance). Points are visited outwards from
+// points[i] in row order, stopping once the row gap alone exceeds the best distance found, which
+// holds for every metric.
+fn nearest(points: &[Point], i: usize, metric: Metric) -> Option<(usize, f64)> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_unstable_by_key(|k| points[*k]);
+    let pos = order.iter().position(|k| *k == i).unwrap();
+
+    let mut best: Option<(usize, f64)> = None;
+    let mut consider = |k: usize| {
+        let row_gap = points[k].0.abs_diff(points[i].0) as f64;
+        if best.is_some_and(|(_, d)| row_gap > d) {
+            return false;
+        }
+        let d = metric.distance(points[i], points[k]);
+        if best.is_none_or(|(_, bd)| d < bd) {
+            best = Some((k, d));
+        }
+        true
+    };
+    let mut up = order[..pos].iter().rev();
+    let mut down = order[pos + 1..].iter();
+    let (mut up_open, mut down_open) = (true, true);
+    while up_open || down_open {
+        if up_open {
+            up_open = up.next().is_some_and(|k| consider(*k));
+        }
+        if down_open {
+            down_open = down.next().is_some_and(|k| consider(*k));
+        }
     }
+    best
+}
 
-    let y_diff;
-    if i.1 > j.1 {
-        y_diff = i.1 - j.1;
-    } else {
-        y_diff = j.1 - i.1;
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!(
+            "./exe <file> [factor...] [--weights <file>] [--metric manhattan|chebyshev|euclidean] \
+             [--pair <i> <j>] [--nearest <i>] [--report <k>]"
+        );
     }
+    let content = read_to_string(&args[1]).unwrap();
+    let universe = Universe::new(content);
 
-    return x_diff + y_diff + (row_exp + col_exp) * (factor - 1);
+    let mut factors: Vec<usize> = Vec::new();
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut nearests: Vec<usize> = Vec::new();
+    let mut weights_file: Option<String> = None;
+    let mut metric = Metric::Manhattan;
+    let mut report: Option<usize> = None;
+    // Galaxies are numbered from 1 on the command line, as in the puzzle text.
+    let galaxy = |s: &String| s.parse::<usize>().unwrap() - 1;
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--pair" => pairs.push((galaxy(iter.next().unwrap()), galaxy(iter.next().unwrap()))),
+            "--nearest" => nearests.push(galaxy(iter.next().unwrap())),
+            "--weights" => weights_file = Some(iter.next().unwrap().clone()),
+            "--report" => report = Some(iter.next().unwrap().parse().unwrap()),
+            "--metric" => {
+                metric = match iter.next().unwrap().as_str() {
+                    "manhattan" => Metric::Manhattan,
+                    "chebyshev" => Metric::Chebyshev,
+                    "euclidean" => Metric::Euclidean,
+                    m => panic!("unknown metric {}", m),
+                }
+            }
+            _ => factors.push(parse_weight(arg)),
+        }
+    }
+    if factors.is_empty() {
+        factors = vec![2, 1000000];
+    }
+
+    for factor in factors {
+        let points = match &weights_file {
+            Some(f) => {
+                universe.expanded_weighted(&Weights::parse(&read_to_string(f).unwrap(), factor))
+            }
+            None => universe.expanded(factor),
+        };
+        if metric == Metric::Manhattan {
+            println!("{}", sum_distances(&points));
+        } else {
+            println!("{}", metric.sum(&points));
+        }
+        for (i, j) in &pairs {
+            println!(
+                "factor {}: distance between {} and {} is {}",
+                factor,
+                i + 1,
+                j + 1,
+                metric.distance(points[*i], points[*j])
+            );
+        }
+        for i in &nearests {
+            if let Some((j, d)) = nearest(&points, *i, metric) {
+                println!(
+                    "factor {}: nearest to {} is {} at distance {}",
+                    factor,
+                    i + 1,
+                    j + 1,
+                    d
+                );
+            }
+        }
+        if let Some(k) = report {
+            let (closest, farthest) = extreme_pairs(&points, metric, k);
+            println!("factor {}: {} closest pairs", factor, k);
+            for (i, j, d) in closest {
+                println!("  {} - {}: {}", i + 1, j + 1, d);
+            }
+            println!("factor {}: {} farthest pairs", factor, k);
+            for (i, j, d) in farthest {
+                println!("  {} - {}: {}", i + 1, j + 1, d);
+            }
+        }
+    }
 }
 
 fn parse_input(content: String) -> Vec<Vec<char>> {
@@ -1,123 +1,312 @@
-use itertools::Itertools;
-use std::collections::HashMap;
+mod nonogram;
+
+use nonogram::{Nonogram, Outcome};
 use std::env;
 use std::fs::read_to_string;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-struct Key {
-    input: String,
-    record: String,
-    hint: Option<char>,
+// Counts arrangements with a suffix DP over (position, group index, run length). The table is a
+// flat Vec that is kept between rows, so a solver only allocates when it sees a larger row.
+#[derive(Default)]
+struct Solver {
+    table: Vec<u64>,
+    groups: usize,
+    max_run: usize,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+impl Solver {
+    fn idx(&self, i: usize, g: usize, r: usize) -> usize {
+        (i * (self.groups + 1) + g) * (self.max_run + 1) + r
     }
-    let content = read_to_string(&args[1]).unwrap();
-    let mut cnt = 0;
-    let mut memo: HashMap<Key, u64> = HashMap::new();
-
-    content.lines().for_each(|line| {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        let row = parts[0];
-        let record: Vec<u64> = parts[1].split(',').map(|e| e.parse().unwrap()).collect();
-        let x = enumerate(row, &record, &mut memo, None);
-        cnt += x;
-    });
-
-    println!("{}", cnt);
-
-    cnt = 0;
-    content.lines().for_each(|line| {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        let row = [parts[0]].repeat(5).join("?");
-        let record: Vec<u64> = parts[1]
-            .split(',')
-            .map(|e| e.parse().unwrap())
-            .collect_vec()
-            .repeat(5);
-        let x = enumerate(&row, &record, &mut memo, None);
-        cnt += x;
-    });
-
-    println!("{}", cnt);
-}
 
-fn enumerate(row: &str, record: &[u64], memo: &mut HashMap<Key, u64>, hint: Option<char>) -> u64 {
-    let key = Key {
-        input: row.to_string(),
-        record: record.iter().map(|e| e.to_string()).join(","),
-        hint,
-    };
+    // ways(i, g, r) is the number of ways to fill row[i..] given that `g` groups are complete and,
+    // if `r` > 0, the current run of `#` belongs to group `g` and has length `r`.
+    fn enumerate(&mut self, row: &[u8], record: &[usize]) -> u64 {
+        let n = row.len();
+        self.groups = record.len();
+        self.max_run = record.iter().copied().max().unwrap_or(0);
+        let size = (n + 1) * (self.groups + 1) * (self.max_run + 1);
+        self.table.clear();
+        self.table.resize(size, 0);
 
-    if let Some(n) = memo.get(&key) {
-        return *n;
+        for g in 0..=self.groups {
+            for r in 0..=self.max_run {
+                let done = (g == self.groups && r == 0)
+                    || (g + 1 == self.groups && record.get(g) == Some(&r));
+                let idx = self.idx(n, g, r);
+                self.table[idx] = u64::from(done);
+            }
+        }
+        for i in (0..n).rev() {
+            for g in 0..=self.groups {
+                for r in 0..=self.max_run {
+                    let mut cnt = 0;
+                    for &c in choices(row[i]) {
+                        if let Some((g, r)) = next_state(record, g, r, c) {
+                            cnt += self.table[self.idx(i + 1, g, r)];
+                        }
+                    }
+                    let idx = self.idx(i, g, r);
+                    self.table[idx] = cnt;
+                }
+            }
+        }
+        self.table[self.idx(0, 0, 0)]
     }
 
-    if record.len() == 0 {
-        if row.chars().any(|c| c == '#') {
-            memo.insert(key, 0);
-            return 0;
-        }
-        memo.insert(key, 1);
-        return 1;
+    fn ways(&self, i: usize, g: usize, r: usize) -> u64 {
+        self.table[self.idx(i, g, r)]
     }
+}
 
-    if row.len() == 0 {
-        memo.insert(key, 0);
-        return 0;
+// The concrete spring states a row character may stand for, in lexicographic order.
+fn choices(c: u8) -> &'static [u8] {
+    match c {
+        b'#' => b"#",
+        b'.' => b".",
+        b'?' => b"#.",
+        _ => unreachable!(),
     }
+}
 
-    match row.chars().nth(0).unwrap() {
-        '.' => {
-            if let Some(v) = hint {
-                if v != '.' {
-                    memo.insert(key, 0);
-                    return 0;
+// The DP state after placing spring `c` in state (g, r), or None if that breaks the record.
+fn next_state(record: &[usize], g: usize, r: usize, c: u8) -> Option<(usize, usize)> {
+    let need = record.get(g).copied();
+    match c {
+        b'#' if need.is_some_and(|need| r < need) => Some((g, r + 1)),
+        b'.' if r == 0 => Some((g, 0)),
+        b'.' if need == Some(r) => Some((g + 1, 0)),
+        _ => None,
+    }
+}
+
+// A single row together with its filled DP table, used to list, rank and sample the concrete
+// arrangements behind the count. Arrangements are ordered lexicographically, so `#` sorts before
+// `.`.
+struct Row {
+    row: Vec<u8>,
+    record: Vec<usize>,
+    solver: Solver,
+    count: u64,
+}
+
+impl Row {
+    fn new(row: &str, record: Vec<usize>) -> Self {
+        let mut solver = Solver::default();
+        let count = solver.enumerate(row.as_bytes(), &record);
+        Row {
+            row: row.as_bytes().to_vec(),
+            record,
+            solver,
+            count,
+        }
+    }
+
+    // The k-th arrangement (0-based). At each position the branch is chosen by comparing k with
+    // the number of completions below the smaller choice.
+    fn unrank(&self, mut k: u64) -> Option<String> {
+        if k >= self.count {
+            return None;
+        }
+        let (mut g, mut r) = (0, 0);
+        let mut out = String::with_capacity(self.row.len());
+        for i in 0..self.row.len() {
+            for &c in choices(self.row[i]) {
+                let Some((ng, nr)) = next_state(&self.record, g, r, c) else {
+                    continue;
+                };
+                let ways = self.solver.ways(i + 1, ng, nr);
+                if k < ways {
+                    (g, r) = (ng, nr);
+                    out.push(c as char);
+                    break;
                 }
+                k -= ways;
             }
-            let cnt = enumerate(&row[1..], record, memo, None);
-            memo.insert(key, cnt);
-            cnt
         }
-        '#' => {
-            if let Some(v) = hint {
-                if v != '#' {
-                    memo.insert(key, 0);
-                    return 0;
+        Some(out)
+    }
+
+    // The position of `arrangement` among all arrangements, or None if it is not one of them.
+    fn rank(&self, arrangement: &str) -> Option<u64> {
+        let arrangement = arrangement.as_bytes();
+        if arrangement.len() != self.row.len() {
+            return None;
+        }
+        let (mut g, mut r) = (0, 0);
+        let mut k = 0;
+        for (i, &want) in arrangement.iter().enumerate() {
+            if !choices(self.row[i]).contains(&want) {
+                return None;
+            }
+            for &c in choices(self.row[i]).iter().take_while(|c| **c != want) {
+                if let Some((ng, nr)) = next_state(&self.record, g, r, c) {
+                    k += self.solver.ways(i + 1, ng, nr);
                 }
             }
-            let cnt;
-            if record[0] == 1 {
-                cnt = enumerate(&row[1..], &record[1..], memo, Some('.'));
-            } else {
-                cnt = enumerate(
-                    &row[1..],
-                    &[&[record[0] - 1], &record[1..]].concat(),
-                    memo,
-                    Some('#'),
-                );
+            (g, r) = next_state(&self.record, g, r, want)?;
+        }
+        (self.solver.ways(self.row.len(), g, r) == 1).then_some(k)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.count).map(|k| self.unrank(k).unwrap())
+    }
+
+    // A uniformly random arrangement, via rejection sampling to avoid modulo bias.
+    fn sample(&self, rng: &mut Rng) -> Option<String> {
+        if self.count == 0 {
+            return None;
+        }
+        let zone = u64::MAX - u64::MAX % self.count;
+        loop {
+            let v = rng.next();
+            if v < zone {
+                return self.unrank(v % self.count);
             }
-            memo.insert(key, cnt);
-            cnt
         }
-        '?' => {
-            let cnt;
-            if let Some(v) = hint {
-                if v == '.' {
-                    cnt = enumerate(&[".", &row[1..]].concat(), record, memo, None);
-                } else {
-                    cnt = enumerate(&["#", &row[1..]].concat(), record, memo, None);
+    }
+}
+
+// xorshift64*, enough for picking sample arrangements.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+}
+
+fn parse_line(line: &str, unfold: usize) -> (String, Vec<usize>) {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let row = [parts[0]].repeat(unfold).join("?");
+    let record: Vec<usize> = parts[1]
+        .split(',')
+        .map(|e| e.parse().unwrap())
+        .collect::<Vec<usize>>()
+        .repeat(unfold);
+    (row, record)
+}
+
+// Sums the arrangement counts of every line, splitting the lines across one thread per core.
+fn total(content: &str, unfold: usize) -> u64 {
+    let lines: Vec<&str> = content.lines().collect();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = lines.len().div_ceil(threads).max(1);
+    thread::scope(|s| {
+        let handles: Vec<_> = lines
+            .chunks(chunk)
+            .map(|chunk| {
+                s.spawn(move || {
+                    let mut solver = Solver::default();
+                    chunk
+                        .iter()
+                        .map(|line| {
+                            let (row, record) = parse_line(line, unfold);
+                            solver.enumerate(row.as_bytes(), &record)
+                        })
+                        .sum::<u64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+fn solve_nonogram(content: &str) {
+    match Nonogram::new(content).solve() {
+        Outcome::Unique(grid) => {
+            println!("unique solution");
+            print!("{}", nonogram::render(&grid));
+        }
+        Outcome::Multiple(a, b) => {
+            println!("multiple solutions, two of them:");
+            print!("{}", nonogram::render(&a));
+            println!();
+            print!("{}", nonogram::render(&b));
+        }
+        Outcome::NoSolution => println!("no solution"),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!(
+            "./exe <file> [unfold] [--list <line>] [--nth <line> <k>] [--rank <line> <arrangement>] \
+             [--sample <line> <n>] [--seed <seed>]\n./exe nonogram <clue file>"
+        );
+    }
+    if args[1] == "nonogram" {
+        solve_nonogram(&read_to_string(&args[2]).unwrap());
+        return;
+    }
+    let content = read_to_string(&args[1]).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    // Lines are numbered from 1 and shown folded, as they appear in the input.
+    let row_at = |s: &String| {
+        let (row, record) = parse_line(lines[s.parse::<usize>().unwrap() - 1], 1);
+        Row::new(&row, record)
+    };
+
+    let mut unfold = 5;
+    let mut seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let mut queries: Vec<&[String]> = Vec::new();
+    let mut i = 2;
+    while i < args.len() {
+        let arity = match args[i].as_str() {
+            "--list" => 2,
+            "--nth" | "--rank" | "--sample" => 3,
+            "--seed" => {
+                seed = args[i + 1].parse().unwrap();
+                i += 2;
+                continue;
+            }
+            _ => {
+                unfold = args[i].parse().unwrap();
+                i += 1;
+                continue;
+            }
+        };
+        queries.push(&args[i..i + arity]);
+        i += arity;
+    }
+
+    println!("{}", total(&content, 1));
+    println!("{}", total(&content, unfold));
+
+    let mut rng = Rng::new(seed);
+    for q in queries {
+        let row = row_at(&q[1]);
+        match q[0].as_str() {
+            "--list" => row.iter().for_each(|a| println!("{}", a)),
+            "--nth" => match row.unrank(q[2].parse().unwrap()) {
+                Some(a) => println!("{}", a),
+                None => println!("line {} has only {} arrangements", q[1], row.count),
+            },
+            "--rank" => match row.rank(&q[2]) {
+                Some(k) => println!("{}", k),
+                None => println!("{} is not an arrangement of line {}", q[2], q[1]),
+            },
+            "--sample" => {
+                for _ in 0..q[2].parse::<usize>().unwrap() {
+                    if let Some(a) = row.sample(&mut rng) {
+                        println!("{}", a);
+                    }
                 }
-            } else {
-                cnt = enumerate(&[".", &row[1..]].concat(), record, memo, None)
-                    + enumerate(&["#", &row[1..]].concat(), record, memo, None);
             }
-            memo.insert(key, cnt);
-            cnt
+            _ => unreachable!(),
         }
-        _ => unreachable!(),
     }
 }
@@ -0,0 +1,173 @@
+use crate::Solver;
+
+// A nonogram (picross) puzzle. Every row and column is a day12 row: the cells are `#`, `.` or `?`
+// and the clue is the record of contiguous `#` groups.
+//
+// Clue files list one row per line as `<cells> <groups>`, exactly like a day12 input line, then a
+// blank line, then one column clue per line. The cells of a row give the width of the puzzle and
+// may already fix some cells. A line without any filled cells has the clue `0`.
+//
+//     ????? 3
+//     ????? 1,1
+//
+//     1
+//     2
+//     ...
+pub struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+    grid: Vec<Vec<u8>>,
+}
+
+pub enum Outcome {
+    Unique(Vec<Vec<u8>>),
+    Multiple(Vec<Vec<u8>>, Vec<Vec<u8>>),
+    NoSolution,
+}
+
+fn parse_clue(clue: &str) -> Vec<usize> {
+    if clue == "0" {
+        return Vec::new();
+    }
+    clue.split(',').map(|e| e.parse().unwrap()).collect()
+}
+
+impl Nonogram {
+    pub fn new(content: &str) -> Self {
+        let (rows, cols) = content
+            .split_once("\n\n")
+            .expect("row and column clues must be separated by a blank line");
+        let mut grid = Vec::new();
+        let mut row_clues = Vec::new();
+        for line in rows.lines() {
+            let (cells, clue) = line.split_once(' ').unwrap();
+            grid.push(cells.as_bytes().to_vec());
+            row_clues.push(parse_clue(clue));
+        }
+        let col_clues: Vec<Vec<usize>> = cols
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(parse_clue)
+            .collect();
+        assert!(
+            grid.iter().all(|r| r.len() == col_clues.len()),
+            "every row must have one cell per column clue"
+        );
+        Nonogram {
+            rows: row_clues,
+            cols: col_clues,
+            grid,
+        }
+    }
+
+    // Finds up to two solutions, which is enough to tell unique from multiple.
+    pub fn solve(&self) -> Outcome {
+        let mut solver = Solver::default();
+        let mut solutions = Vec::new();
+        self.search(&mut solver, self.grid.clone(), &mut solutions);
+        let mut solutions = solutions.into_iter();
+        match (solutions.next(), solutions.next()) {
+            (None, _) => Outcome::NoSolution,
+            (Some(a), None) => Outcome::Unique(a),
+            (Some(a), Some(b)) => Outcome::Multiple(a, b),
+        }
+    }
+
+    // Propagates as far as line solving goes, then guesses the first unknown cell.
+    fn search(
+        &self,
+        solver: &mut Solver,
+        mut grid: Vec<Vec<u8>>,
+        solutions: &mut Vec<Vec<Vec<u8>>>,
+    ) {
+        if !self.propagate(solver, &mut grid) {
+            return;
+        }
+        let unknown = grid
+            .iter()
+            .enumerate()
+            .find_map(|(x, row)| row.iter().position(|c| *c == b'?').map(|y| (x, y)));
+        let Some((x, y)) = unknown else {
+            solutions.push(grid);
+            return;
+        };
+        for c in [b'#', b'.'] {
+            let mut guess = grid.clone();
+            guess[x][y] = c;
+            self.search(solver, guess, solutions);
+            if solutions.len() >= 2 {
+                return;
+            }
+        }
+    }
+
+    // Line-solves dirty rows and columns until nothing changes. Returns false on a contradiction.
+    fn propagate(&self, solver: &mut Solver, grid: &mut [Vec<u8>]) -> bool {
+        let mut dirty_rows = vec![true; self.rows.len()];
+        let mut dirty_cols = vec![true; self.cols.len()];
+        while dirty_rows.iter().any(|d| *d) || dirty_cols.iter().any(|d| *d) {
+            for x in 0..self.rows.len() {
+                if !std::mem::take(&mut dirty_rows[x]) {
+                    continue;
+                }
+                let Some(changed) = solve_line(solver, &mut grid[x], &self.rows[x]) else {
+                    return false;
+                };
+                changed.into_iter().for_each(|y| dirty_cols[y] = true);
+            }
+            for y in 0..self.cols.len() {
+                if !std::mem::take(&mut dirty_cols[y]) {
+                    continue;
+                }
+                let mut col: Vec<u8> = grid.iter().map(|row| row[y]).collect();
+                let Some(changed) = solve_line(solver, &mut col, &self.cols[y]) else {
+                    return false;
+                };
+                for x in changed {
+                    grid[x][y] = col[x];
+                    dirty_rows[x] = true;
+                }
+            }
+        }
+        true
+    }
+}
+
+// Fixes every unknown cell of `line` that takes the same value in all arrangements, i.e. where
+// one of the two choices leaves no arrangement at all. Returns the indices of the fixed cells, or
+// None if the line has no arrangement.
+fn solve_line(solver: &mut Solver, line: &mut [u8], record: &[usize]) -> Option<Vec<usize>> {
+    if solver.enumerate(line, record) == 0 {
+        return None;
+    }
+    let mut changed = Vec::new();
+    for i in 0..line.len() {
+        if line[i] != b'?' {
+            continue;
+        }
+        line[i] = b'#';
+        let filled = solver.enumerate(line, record);
+        line[i] = b'.';
+        let empty = solver.enumerate(line, record);
+        line[i] = match (filled, empty) {
+            (_, 0) => b'#',
+            (0, _) => b'.',
+            _ => b'?',
+        };
+        if line[i] != b'?' {
+            changed.push(i);
+        }
+    }
+    Some(changed)
+}
+
+pub fn render(grid: &[Vec<u8>]) -> String {
+    let mut out = String::new();
+    for row in grid {
+        for c in row {
+            out.push_str(if *c == b'#' { "██" } else { "  " });
+        }
+        out.push('\n');
+    }
+    out
+}
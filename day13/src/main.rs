@@ -1,133 +1,285 @@
-use array2d::Array2D;
-use std::cmp::min;
-use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::read_to_string;
+use std::time::Instant;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Point {
-    Ash,
-    Rock,
+enum Axis {
+    // A mirror between two columns.
+    Vertical,
+    // A mirror between two rows.
+    Horizontal,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
-    }
-    let mut content = read_to_string(&args[1]).unwrap();
-    content.push_str("\n");
-
-    let mut space: Vec<Vec<Point>> = Vec::new();
-    let mut sum1 = 0;
-    let mut sum2 = 0;
-    content.lines().for_each(|line| {
-        if line.is_empty() {
-            sum1 += part1(Array2D::from_rows(&space).unwrap());
-            sum2 += part2(Array2D::from_rows(&space).unwrap());
-            space.clear();
-            return;
+impl Axis {
+    // The puzzle summary counts columns left of a vertical mirror once and rows above a
+    // horizontal mirror a hundred times.
+    fn weight(self) -> u64 {
+        match self {
+            Axis::Vertical => 1,
+            Axis::Horizontal => 100,
         }
-        let line = line
-            .chars()
-            .map(|c| match c {
-                '.' => Point::Ash,
-                '#' => Point::Rock,
-                _ => unreachable!(),
-            })
-            .collect();
-        space.push(line);
-    });
+    }
+}
 
-    println!("{}", sum1);
-    println!("{}", sum2);
+#[derive(Debug)]
+struct Reflection {
+    axis: Axis,
+    // Number of columns left of (or rows above) the mirror.
+    pos: usize,
+    // Pairs of mirrored (row, column) cells that differ. Flipping either cell of a pair fixes it.
+    smudges: Vec<((usize, usize), (usize, usize))>,
 }
 
-fn part1(space: Array2D<Point>) -> u64 {
-    let row_score = scan_lines(space.as_rows(), 1);
-    if row_score != 0 {
-        row_score
-    } else {
-        scan_lines(space.as_columns(), 100)
+impl Reflection {
+    fn mismatches(&self) -> usize {
+        self.smudges.len()
     }
 }
 
-fn scan_lines(lines: Vec<Vec<Point>>, factor: u64) -> u64 {
-    let mut mirror: Option<HashSet<usize>> = None;
-    for line in lines {
-        let set = find_mirror(&line);
-        if mirror.is_none() {
-            mirror = Some(set);
-            continue;
+// One row or column of a pattern as a set of rock positions.
+trait Line: Sized {
+    fn new(len: usize) -> Self;
+    fn set(&mut self, i: usize);
+    // Number of positions where the two lines differ.
+    fn mismatches(&self, other: &Self) -> u32;
+    // The positions where the two lines differ.
+    fn diff(&self, other: &Self) -> Vec<usize>;
+}
+
+impl Line for u128 {
+    fn new(_len: usize) -> Self {
+        0
+    }
+
+    fn set(&mut self, i: usize) {
+        *self |= 1 << i;
+    }
+
+    fn mismatches(&self, other: &Self) -> u32 {
+        (self ^ other).count_ones()
+    }
+
+    fn diff(&self, other: &Self) -> Vec<usize> {
+        let mut x = self ^ other;
+        let mut out = Vec::new();
+        while x != 0 {
+            out.push(x.trailing_zeros() as usize);
+            x &= x - 1;
         }
-        if let Some(ref mut mirror) = mirror {
-            mirror.retain(|e| set.contains(e));
-            if mirror.len() == 0 {
-                break;
+        out
+    }
+}
+
+// Fallback for lines longer than 128 cells.
+struct BitSet(Vec<u64>);
+
+impl Line for BitSet {
+    fn new(len: usize) -> Self {
+        BitSet(vec![0; len.div_ceil(64)])
+    }
+
+    fn set(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn mismatches(&self, other: &Self) -> u32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+
+    fn diff(&self, other: &Self) -> Vec<usize> {
+        let mut out = Vec::new();
+        for (w, (a, b)) in self.0.iter().zip(&other.0).enumerate() {
+            let mut x = a ^ b;
+            while x != 0 {
+                out.push(w * 64 + x.trailing_zeros() as usize);
+                x &= x - 1;
             }
         }
+        out
     }
-    if let Some(v) = mirror.unwrap().iter().next() {
-        return *v as u64 * factor;
+}
+
+// A pattern stored both row-wise and column-wise, so that mirrors along either axis compare whole
+// lines at once.
+struct Pattern<L: Line> {
+    rows: Vec<L>,
+    cols: Vec<L>,
+}
+
+impl<L: Line> Pattern<L> {
+    fn new(rocks: &[Vec<bool>]) -> Self {
+        let (height, width) = (rocks.len(), rocks[0].len());
+        let mut rows: Vec<L> = (0..height).map(|_| L::new(width)).collect();
+        let mut cols: Vec<L> = (0..width).map(|_| L::new(height)).collect();
+        for (x, line) in rocks.iter().enumerate() {
+            for (y, rock) in line.iter().enumerate() {
+                if *rock {
+                    rows[x].set(y);
+                    cols[y].set(x);
+                }
+            }
+        }
+        Pattern { rows, cols }
     }
 
-    return 0;
+    // Every vertical and horizontal mirror with at most `k` mismatched cell pairs.
+    fn reflections(&self, k: usize) -> Vec<Reflection> {
+        let mut found = Vec::new();
+        // A vertical mirror reflects columns onto columns and a horizontal one rows onto rows.
+        for (axis, lines) in [(Axis::Vertical, &self.cols), (Axis::Horizontal, &self.rows)] {
+            for pos in 1..lines.len() {
+                if let Some(smudges) = mirror_smudges(lines, pos, k) {
+                    let smudges = smudges
+                        .into_iter()
+                        .map(|(a, b, i)| match axis {
+                            Axis::Vertical => ((i, a), (i, b)),
+                            Axis::Horizontal => ((a, i), (b, i)),
+                        })
+                        .collect();
+                    found.push(Reflection { axis, pos, smudges });
+                }
+            }
+        }
+        found
+    }
 }
 
-fn find_mirror(line: &[Point]) -> HashSet<usize> {
-    let mut set = HashSet::new();
-    for i in 1..line.len() {
-        let size = min(i, line.len() - i);
-        if line[0..i]
-            .iter()
-            .rev()
-            .take(size)
-            .eq(line[i..line.len()].iter().take(size))
-        {
-            set.insert(i);
+// Compares the lines mirrored at `pos`, returning the mismatches as (line, mirrored line, index
+// within the lines), or None as soon as there are more than `k` of them.
+fn mirror_smudges<L: Line>(
+    lines: &[L],
+    pos: usize,
+    k: usize,
+) -> Option<Vec<(usize, usize, usize)>> {
+    let size = pos.min(lines.len() - pos);
+    let mut total = 0;
+    for d in 0..size {
+        total += lines[pos - 1 - d].mismatches(&lines[pos + d]) as usize;
+        if total > k {
+            return None;
         }
     }
-    set
+    let mut smudges = Vec::new();
+    for d in 0..size {
+        let (a, b) = (pos - 1 - d, pos + d);
+        for i in lines[a].diff(&lines[b]) {
+            smudges.push((a, b, i));
+        }
+    }
+    Some(smudges)
 }
 
-fn part2(space: Array2D<Point>) -> u64 {
-    let row_score = scan_lines2(space.as_rows(), 1);
-    if row_score != 0 {
-        row_score
+fn reflections(rocks: &[Vec<bool>], k: usize) -> Vec<Reflection> {
+    if rocks.len() <= 128 && rocks[0].len() <= 128 {
+        Pattern::<u128>::new(rocks).reflections(k)
     } else {
-        scan_lines2(space.as_columns(), 100)
+        Pattern::<BitSet>::new(rocks).reflections(k)
     }
 }
 
-fn scan_lines2(lines: Vec<Vec<Point>>, factor: u64) -> u64 {
-    let mut dist_vec_map: HashMap<usize, Vec<u64>> = HashMap::new();
-    for line in lines {
-        for (idx, dist) in mirror_distance(&line) {
-            let dist_vec = dist_vec_map.entry(idx).or_insert(Vec::new());
-            dist_vec.push(dist);
+// A random `size` x `size` pattern that mirrors horizontally after its first third of rows.
+fn generate(size: usize) -> Vec<Vec<bool>> {
+    let mut seed: u64 = 0x9E3779B97F4A7C15;
+    let mut rand = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed & 1 == 1
+    };
+    let mirror = size / 3;
+    let mut rocks: Vec<Vec<bool>> = (0..size)
+        .map(|_| (0..size).map(|_| rand()).collect())
+        .collect();
+    for d in 0..mirror.min(size - mirror) {
+        rocks[mirror + d] = rocks[mirror - 1 - d].clone();
+    }
+    rocks
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("./exe <file> [tolerance...] [--report]\n./exe --bench <size>");
+    }
+    if args[1] == "--bench" {
+        let size: usize = args[2].parse().unwrap();
+        let rocks = generate(size);
+        let start = Instant::now();
+        let found = reflections(&rocks, 0);
+        let elapsed = start.elapsed();
+        for r in found {
+            println!("{:?} mirror at {}", r.axis, r.pos);
         }
+        println!("{}x{} pattern searched in {:?}", size, size, elapsed);
+        return;
+    }
+    let mut content = read_to_string(&args[1]).unwrap();
+    content.push('\n');
+    let report = args[2..].iter().any(|a| a == "--report");
+    let mut tolerances: Vec<usize> = args[2..]
+        .iter()
+        .filter(|a| *a != "--report")
+        .map(|a| a.parse().unwrap())
+        .collect();
+    if tolerances.is_empty() {
+        tolerances = vec![0, 1];
     }
+    let max_tolerance = *tolerances.iter().max().unwrap();
 
-    for (idx, dist_vec) in dist_vec_map {
-        if dist_vec.iter().sum::<u64>() == 1 {
-            return idx as u64 * factor;
+    let mut space: Vec<Vec<bool>> = Vec::new();
+    let mut patterns: Vec<Vec<Vec<bool>>> = Vec::new();
+    content.lines().for_each(|line| {
+        if line.is_empty() {
+            if !space.is_empty() {
+                patterns.push(std::mem::take(&mut space));
+            }
+            return;
+        }
+        let line = line
+            .chars()
+            .map(|c| match c {
+                '.' => false,
+                '#' => true,
+                _ => unreachable!(),
+            })
+            .collect();
+        space.push(line);
+    });
+
+    let mut sums = vec![0; tolerances.len()];
+    for (n, pattern) in patterns.iter().enumerate() {
+        let found = reflections(pattern, max_tolerance);
+        for (sum, k) in sums.iter_mut().zip(&tolerances) {
+            *sum += summarize(&found, *k);
+        }
+        if report {
+            for r in &found {
+                println!(
+                    "pattern {}: {:?} mirror at {} with {} mismatches {:?}",
+                    n + 1,
+                    r.axis,
+                    r.pos,
+                    r.mismatches(),
+                    r.smudges
+                );
+            }
         }
     }
 
-    return 0;
+    for sum in sums {
+        println!("{}", sum);
+    }
 }
 
-fn mirror_distance(line: &[Point]) -> HashMap<usize, u64> {
-    let mut m = HashMap::new();
-    for i in 1..line.len() {
-        let size = min(i, line.len() - i);
-        let dist = line[0..i]
-            .iter()
-            .rev()
-            .take(size)
-            .zip(line[i..line.len()].iter().take(size))
-            .fold(0, |acc, (x, y)| if x == y { acc } else { acc + 1 });
-        m.insert(i, dist);
-    }
-    m
+// The puzzle's summary for mirrors with exactly `k` smudges.
+fn summarize(found: &[Reflection], k: usize) -> u64 {
+    found
+        .iter()
+        .filter(|r| r.mismatches() == k)
+        .map(|r| r.pos as u64 * r.axis.weight())
+        .sum()
 }
@@ -1,16 +1,50 @@
+use std::collections::HashMap;
 use std::env;
-use std::fs::read_to_string;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::io::{stdout, Write};
+use std::thread::sleep;
+use std::time::Duration;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Point {
-    Round,
-    Ground,
-    Cube,
+// A set of cells, one bit per cell in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Bitboard(Vec<u64>);
+
+impl Bitboard {
+    fn new(cells: usize) -> Self {
+        Bitboard(vec![0; cells.div_ceil(64)])
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn clear(&mut self, i: usize) {
+        self.0[i / 64] &= !(1 << (i % 64));
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(w, word)| {
+            let mut x = *word;
+            std::iter::from_fn(move || {
+                if x == 0 {
+                    return None;
+                }
+                let i = w * 64 + x.trailing_zeros() as usize;
+                x &= x - 1;
+                Some(i)
+            })
+        })
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Space {
-    points: Vec<Point>,
+    round: Bitboard,
+    cube: Bitboard,
     width: usize,
     height: usize,
 }
@@ -23,191 +57,295 @@ enum Direction {
     E,
 }
 
-#[derive(Debug, Eq, PartialEq)]
-struct Record {
-    digest: String,
-    load: usize,
-}
-
-struct InplaceVec<'a, T>(&'a mut [&'a mut T]);
-
-impl<'a, T> InplaceVec<'a, T>
-where
-    T: Ord + Copy,
-{
-    fn new(v: &'a mut [&'a mut T]) -> Self {
-        Self(v)
-    }
-
-    fn sort(&mut self) {
-        // buble...
-        for i in 0..self.0.len().saturating_sub(1) {
-            for j in i..self.0.len() {
-                let lh = &self.0[i];
-                let rh = &self.0[j];
-                if **rh < **lh {
-                    let tmp = *self.0[j];
-                    *self.0[j] = *self.0[i];
-                    *self.0[i] = tmp;
-                }
-            }
-        }
-    }
-
-    fn reverse(&mut self) {
-        for i in 0..self.0.len() / 2 {
-            let j = self.0.len() - 1 - i;
-            let tmp = *self.0[i];
-            *self.0[i] = *self.0[j];
-            *self.0[j] = tmp;
+impl Direction {
+    fn new(c: char) -> Self {
+        match c {
+            'N' => Direction::N,
+            'S' => Direction::S,
+            'W' => Direction::W,
+            'E' => Direction::E,
+            _ => panic!("unknown direction {}", c),
         }
     }
 }
 
 impl Space {
     fn new(input: &str) -> Self {
-        let mut points = Vec::new();
-        let mut height = 0;
-        input.lines().for_each(|line| {
-            height += 1;
-            line.chars().for_each(|c| {
-                let p = match c {
-                    '.' => Point::Ground,
-                    '#' => Point::Cube,
-                    'O' => Point::Round,
-                    _ => unreachable!(),
-                };
-                points.push(p);
+        let height = input.lines().count();
+        let width = input.lines().next().map_or(0, |l| l.len());
+        let mut round = Bitboard::new(width * height);
+        let mut cube = Bitboard::new(width * height);
+        input.lines().enumerate().for_each(|(x, line)| {
+            line.chars().enumerate().for_each(|(y, c)| match c {
+                '.' => {}
+                '#' => cube.set(x * width + y),
+                'O' => round.set(x * width + y),
+                _ => unreachable!(),
             });
         });
-        let width = input.len() / height - 1; // 1 is the newline
         Self {
-            points,
+            round,
+            cube,
             width,
             height,
         }
     }
 
+    // Rolls every round rock towards `direction` in a single pass. Each line is walked starting
+    // at the wall the rocks roll to, keeping track of the next free cell: a cube rock resets it
+    // to the cell behind the cube, and a round rock moves into it.
     fn tilt(&mut self, direction: Direction) {
-        let step;
-        let skip_unit;
-        let cycle;
-        let rev;
-        let size;
-        match direction {
-            Direction::N => {
-                step = self.width;
-                skip_unit = 1;
-                cycle = self.width;
-                rev = false;
-                size = self.height;
-            }
-            Direction::S => {
-                step = self.width;
-                skip_unit = 1;
-                cycle = self.width;
-                rev = true;
-                size = self.height;
-            }
-            Direction::W => {
-                step = 1;
-                skip_unit = self.width;
-                cycle = self.height;
-                rev = false;
-                size = self.width;
-            }
-            Direction::E => {
-                step = 1;
-                skip_unit = self.width;
-                cycle = self.height;
-                rev = true;
-                size = self.width;
+        let (w, h) = (self.width as isize, self.height as isize);
+        let (lines, len) = match direction {
+            Direction::N | Direction::S => (w, h),
+            Direction::W | Direction::E => (h, w),
+        };
+        for i in 0..lines {
+            // First cell of the line and the step along it, both in row-major cell indices.
+            let (start, step) = match direction {
+                Direction::N => (i, w),
+                Direction::S => ((h - 1) * w + i, -w),
+                Direction::W => (i * w, 1),
+                Direction::E => (i * w + w - 1, -1),
+            };
+            let mut free = start;
+            for j in 0..len {
+                let cell = start + j * step;
+                if self.cube.get(cell as usize) {
+                    free = cell + step;
+                } else if self.round.get(cell as usize) {
+                    self.round.clear(cell as usize);
+                    self.round.set(free as usize);
+                    free += step;
+                }
             }
         }
-        for i in 0..cycle {
-            let mut l: Vec<&mut Point> = self
-                .points
-                .iter_mut()
-                .skip(i * skip_unit)
-                .step_by(step)
-                .take(size)
-                .collect();
-            l.split_mut(|p| **p == Point::Cube).for_each(|part| {
-                let mut part = InplaceVec::new(part);
-                part.sort();
-                if rev {
-                    part.reverse();
+    }
+
+    // Total load of the round rocks on the `wall` side: a rock counts as many as there are rows
+    // (or columns) from it to the opposite edge, itself included.
+    fn load(&self, wall: Direction) -> usize {
+        self.round
+            .iter()
+            .map(|i| {
+                let (x, y) = (i / self.width, i % self.width);
+                match wall {
+                    Direction::N => self.height - x,
+                    Direction::S => x + 1,
+                    Direction::W => self.width - y,
+                    Direction::E => y + 1,
                 }
-            });
+            })
+            .sum()
+    }
+
+    fn digest(&self) -> String {
+        (0..self.width * self.height)
+            .map(|i| {
+                if self.round.get(i) {
+                    'O'
+                } else if self.cube.get(i) {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+}
+
+// The platform right after one tilt of the spin program.
+struct Frame {
+    // 0-based index of the spin this tilt belongs to.
+    spin: usize,
+    tilt: Direction,
+    digest: String,
+}
+
+// The platform state after spin `start` (0-based) comes back every `period` spins.
+struct Cycle {
+    start: usize,
+    period: usize,
+}
+
+impl Cycle {
+    fn annotate(&self, spin: usize) -> String {
+        if spin == self.start {
+            format!(" [enters cycle, period {}]", self.period)
+        } else if spin > self.start {
+            format!(" [in cycle, period {}]", self.period)
+        } else {
+            String::new()
         }
     }
+}
 
-    fn load(&self) -> usize {
-        let mut n = 0;
-        for i in 0..self.width {
-            n += self
-                .points
-                .iter()
-                .skip(i)
-                .step_by(self.width)
-                .enumerate()
-                .fold(0, |acc, (i, e)| {
-                    if *e == Point::Round {
-                        acc + self.width - i
-                    } else {
-                        acc
-                    }
+// Applies `program` `times` times and returns the final load on `wall`. Since the rocks soon
+// settle into a cycle, states are remembered so the remaining repetitions can be skipped. If
+// `frames` is given, the platform is recorded after every tilt.
+fn run(
+    space: &mut Space,
+    program: &[Direction],
+    times: usize,
+    wall: Direction,
+    mut frames: Option<&mut Vec<Frame>>,
+) -> (usize, Option<Cycle>) {
+    let mut seen: HashMap<Bitboard, usize> = HashMap::new();
+    let mut loads: Vec<usize> = Vec::new();
+    for i in 0..times {
+        program.iter().for_each(|d| {
+            space.tilt(*d);
+            if let Some(frames) = frames.as_mut() {
+                frames.push(Frame {
+                    spin: i,
+                    tilt: *d,
+                    digest: space.digest(),
                 });
+            }
+        });
+        if let Some(first_idx) = seen.get(&space.round) {
+            let cycle = i - first_idx;
+            let load = loads[first_idx + (times - 1 - first_idx) % cycle];
+            return (
+                load,
+                Some(Cycle {
+                    start: *first_idx,
+                    period: cycle,
+                }),
+            );
         }
-        n
+        seen.insert(space.round.clone(), i);
+        loads.push(space.load(wall));
     }
+    (space.load(wall), None)
+}
 
-    fn digest(&self) -> String {
-        self.points
-            .iter()
-            .map(|e| match e {
-                Point::Round => "O",
-                Point::Ground => ".",
-                Point::Cube => "#",
-            })
-            .collect()
+fn frame_text(frame: &Frame, width: usize, cycle: &Option<Cycle>) -> String {
+    let mut out = format!("spin {} tilt {:?}", frame.spin + 1, frame.tilt);
+    if let Some(cycle) = cycle {
+        out.push_str(&cycle.annotate(frame.spin));
+    }
+    out.push('\n');
+    for line in frame.digest.as_bytes().chunks(width) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out
+}
+
+// Writes one binary PPM per frame, `scale` pixels per cell, with `tilts` frames to each spin.
+// Frames inside the cycle get a blue tinted ground so the repeating part stands out.
+fn write_ppm(
+    frames: &[Frame],
+    dir: &str,
+    width: usize,
+    tilts: usize,
+    cycle: &Option<Cycle>,
+    scale: usize,
+) {
+    create_dir_all(dir).unwrap();
+    for (n, frame) in frames.iter().enumerate() {
+        let height = frame.digest.len() / width;
+        let in_cycle = cycle.as_ref().is_some_and(|c| frame.spin >= c.start);
+        let mut data = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+        let cells = frame.digest.as_bytes();
+        for x in 0..height * scale {
+            for y in 0..width * scale {
+                let rgb: [u8; 3] = match cells[(x / scale) * width + y / scale] {
+                    b'O' => [230, 120, 30],
+                    b'#' => [60, 60, 60],
+                    _ if in_cycle => [200, 215, 240],
+                    _ => [235, 235, 235],
+                };
+                data.extend_from_slice(&rgb);
+            }
+        }
+        write(format!("{}/frame_{:05}.ppm", dir, n), data).unwrap();
+    }
+    if let Some(cycle) = cycle {
+        // The frame of the spin's last tilt, which is the state that repeats. An empty program
+        // records no frames.
+        let frame = match (cycle.start + 1) * tilts {
+            0 => String::new(),
+            n => format!(" (frame {})", n - 1),
+        };
+        write(
+            format!("{}/cycle.txt", dir),
+            format!(
+                "cycle starts at spin {}{}, period {} spins\n",
+                cycle.start + 1,
+                frame,
+                cycle.period
+            ),
+        )
+        .unwrap();
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        panic!(
+            "./exe <file> [--spin <directions>] [--times <n>] [--load <wall>] \
+             [--frames text|play|ppm] [--delay <ms>] [--out <dir>]"
+        );
     }
     let content = read_to_string(&args[1]).unwrap();
+    let mut program: Vec<Direction> = "NWSE".chars().map(Direction::new).collect();
+    let mut times = 1000000000;
+    let mut wall = Direction::N;
+    let mut export: Option<String> = None;
+    let mut delay = 100;
+    let mut out_dir = "frames".to_string();
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next().unwrap();
+        match arg.as_str() {
+            "--spin" => program = value.chars().map(Direction::new).collect(),
+            "--times" => times = value.parse().unwrap(),
+            "--load" => wall = Direction::new(value.chars().next().unwrap()),
+            "--frames" => export = Some(value.clone()),
+            "--delay" => delay = value.parse().unwrap(),
+            "--out" => out_dir = value.clone(),
+            _ => panic!("unknown option {}", arg),
+        }
+    }
 
     let mut space = Space::new(&content);
     space.tilt(Direction::N);
-    println!("{}", space.load());
+    println!("{}", space.load(Direction::N));
 
-    space = Space::new(&content);
-    let mut records: Vec<Record> = Vec::new();
-    let loop_cnt = 1000000000;
-    for i in 0..loop_cnt {
-        space.tilt(Direction::N);
-        space.tilt(Direction::W);
-        space.tilt(Direction::S);
-        space.tilt(Direction::E);
-        let record = Record {
-            digest: space.digest(),
-            load: space.load(),
-        };
-        //dbg!(space.load());
-        if let Some(first_idx) = records.iter().position(|e| e.digest == record.digest) {
-            let cycle = i - first_idx;
-            println!(
-                "{}",
-                records[first_idx + (loop_cnt - 1 - first_idx) % cycle].load
-            );
-            //dbg!(first_idx, i, cycle);
-            break;
-        } else {
-            records.push(record);
+    let mut space = Space::new(&content);
+    let mut frames = Vec::new();
+    let (load, cycle) = run(
+        &mut space,
+        &program,
+        times,
+        wall,
+        export.is_some().then_some(&mut frames),
+    );
+    println!("{}", load);
+
+    let width = space.width;
+    match export.as_deref() {
+        None => {}
+        Some("text") => frames
+            .iter()
+            .for_each(|f| println!("{}", frame_text(f, width, &cycle))),
+        Some("play") => {
+            for f in &frames {
+                // Clear the screen and move the cursor home before drawing the next frame.
+                print!("\x1b[2J\x1b[H{}", frame_text(f, width, &cycle));
+                stdout().flush().unwrap();
+                sleep(Duration::from_millis(delay));
+            }
+        }
+        Some("ppm") => write_ppm(&frames, &out_dir, width, program.len(), &cycle, 4),
+        Some(other) => panic!("unknown frame format {}", other),
+    }
+    if export.is_some() {
+        if let Some(c) = &cycle {
+            println!("cycle starts at spin {}, period {}", c.start + 1, c.period);
         }
     }
 }
@@ -229,8 +367,8 @@ O....#OO..
 O.........
 #....###..
 #OO..#...."#;
-    let w_space = Space::new(&w_content);
-    assert_eq!(space.points, w_space.points);
+    let w_space = Space::new(w_content);
+    assert_eq!(space, w_space);
 
     let mut space = Space::new(&content);
     space.tilt(Direction::E);
@@ -244,8 +382,8 @@ O.........
 .........O
 #....###..
 #..OO#...."#;
-    let e_space = Space::new(&e_content);
-    assert_eq!(space.points, e_space.points);
+    let e_space = Space::new(e_content);
+    assert_eq!(space, e_space);
 
     let mut space = Space::new(&content);
     space.tilt(Direction::S);
@@ -259,6 +397,6 @@ O....#....
 OO....OO..
 #OO..###..
 #OO.O#...O"#;
-    let s_space = Space::new(&s_content);
-    assert_eq!(space.points, s_space.points);
+    let s_space = Space::new(s_content);
+    assert_eq!(space, s_space);
 }
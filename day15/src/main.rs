@@ -1,50 +1,62 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::process::exit;
 
+// An insertion-ordered set of lenses. Removing a lens only leaves a hole in `slots`, and the holes
+// are squeezed out once they make up half of the slots, so add, replace and remove are all O(1)
+// amortised.
 #[derive(Debug)]
 struct Box {
-    lens: Vec<Len>,
-    // Stores the label -> index in the lens list for each len
+    slots: Vec<Option<Len>>,
+    // Stores the label -> index in the slot list for each len
     idx_map: HashMap<String, usize>,
 }
 
 impl Box {
     fn new() -> Self {
         Self {
-            lens: Vec::new(),
+            slots: Vec::new(),
             idx_map: HashMap::new(),
         }
     }
 
     fn add(&mut self, label: &str, focal_length: u64) {
-        let label = label.to_string();
-        let len = Len {
-            label,
-            focal_length,
-        };
-        if let Some(idx) = self.idx_map.get(&len.label) {
-            let mut iter = self.lens.iter_mut();
-            for _ in 0..*idx {
-                iter.next();
-            }
-            let v = iter.next().unwrap();
-            *v = len;
+        if let Some(idx) = self.idx_map.get(label) {
+            self.slots[*idx].as_mut().unwrap().focal_length = focal_length;
         } else {
-            self.idx_map.insert(len.label.clone(), self.lens.len());
-            self.lens.push(len);
+            self.idx_map.insert(label.to_string(), self.slots.len());
+            self.slots.push(Some(Len {
+                label: label.to_string(),
+                focal_length,
+            }));
         }
     }
 
     fn remove(&mut self, label: &str) {
         if let Some(idx) = self.idx_map.remove(label) {
-            self.lens.remove(idx);
-            self.idx_map
-                .values_mut()
-                .filter(|&&mut v| v > idx)
-                .for_each(|v| *v -= 1);
+            self.slots[idx] = None;
+            if self.slots.len() >= 2 * self.idx_map.len() {
+                self.compact();
+            }
+        }
+    }
+
+    fn compact(&mut self) {
+        self.slots.retain(|s| s.is_some());
+        for (idx, len) in self.slots.iter().enumerate() {
+            *self.idx_map.get_mut(&len.as_ref().unwrap().label).unwrap() = idx;
         }
     }
+
+    fn lens(&self) -> impl Iterator<Item = &Len> {
+        self.slots.iter().flatten()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.idx_map.is_empty()
+    }
 }
 
 #[derive(Debug)]
@@ -53,42 +65,317 @@ struct Len {
     focal_length: u64,
 }
 
+// The HASH algorithm with its constants exposed: for every character, add its code, multiply by
+// `multiplier` and take the remainder modulo `modulus`. The puzzle uses 17 and 256.
+#[derive(Debug, Copy, Clone)]
+struct Hasher {
+    multiplier: usize,
+    modulus: usize,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self {
+            multiplier: 17,
+            modulus: 256,
+        }
+    }
+}
+
+impl Hasher {
+    fn hash(&self, str: &str) -> usize {
+        str.chars().fold(0, |acc, c| {
+            (acc + c as usize) * self.multiplier % self.modulus
+        })
+    }
+}
+
+struct LensLibrary {
+    hasher: Hasher,
+    boxes: Vec<Box>,
+}
+
+impl LensLibrary {
+    fn new(hasher: Hasher, boxes: usize) -> Self {
+        Self {
+            hasher,
+            boxes: (0..boxes).map(|_| Box::new()).collect(),
+        }
+    }
+
+    fn box_of(&self, label: &str) -> usize {
+        self.hasher.hash(label) % self.boxes.len()
+    }
+
+    // Runs one operation, returning what it has to report for lookups and dumps.
+    fn execute(&mut self, op: &Op) -> Option<String> {
+        match op {
+            Op::Add(label, focal_length) => {
+                let idx = self.box_of(label);
+                self.boxes[idx].add(label, *focal_length);
+                None
+            }
+            Op::Remove(label) => {
+                let idx = self.box_of(label);
+                self.boxes[idx].remove(label);
+                None
+            }
+            Op::Lookup(label) => {
+                let idx = self.box_of(label);
+                let found = self.boxes[idx]
+                    .lens()
+                    .enumerate()
+                    .find(|(_, len)| len.label == *label);
+                Some(match found {
+                    Some((slot, len)) => format!(
+                        "{}: box {} slot {} focal length {}\n",
+                        label,
+                        idx,
+                        slot + 1,
+                        len.focal_length
+                    ),
+                    None => format!("{}: not in box {}\n", label, idx),
+                })
+            }
+            Op::Clear(idx) => {
+                if let Some(boks) = self.boxes.get_mut(*idx) {
+                    *boks = Box::new();
+                }
+                None
+            }
+            Op::Dump => Some(self.describe()),
+        }
+    }
+
+    fn focusing_power(&self) -> usize {
+        self.boxes
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (box_idx, boks)| {
+                acc + boks.lens().enumerate().fold(0, |acc, (len_idx, len)| {
+                    acc + (box_idx + 1) * (len_idx + 1) * len.focal_length as usize
+                })
+            })
+    }
+
+    // The non-empty boxes in the same form as the puzzle text, e.g. `Box 0: [rn 1] [cm 2]`.
+    fn describe(&self) -> String {
+        let mut out = String::new();
+        for (idx, boks) in self.boxes.iter().enumerate() {
+            if boks.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("Box {}:", idx));
+            for len in boks.lens() {
+                out.push_str(&format!(" [{} {}]", len.label, len.focal_length));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// How evenly the library's hasher spreads the distinct labels of the sequence over its boxes.
+fn hash_stats(library: &LensLibrary, ops: &[Op]) -> String {
+    let labels: HashSet<&str> = ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::Add(label, _) | Op::Remove(label) | Op::Lookup(label) => Some(label.as_str()),
+            Op::Clear(_) | Op::Dump => None,
+        })
+        .collect();
+    let boxes = library.boxes.len();
+    let mut counts = vec![0usize; boxes];
+    labels
+        .iter()
+        .for_each(|label| counts[library.box_of(label)] += 1);
+
+    let used = counts.iter().filter(|c| **c > 0).count();
+    let max = counts.iter().max().unwrap();
+    let colliding_pairs: usize = counts.iter().map(|c| c * c.saturating_sub(1) / 2).sum();
+    let expected = labels.len() as f64 / boxes as f64;
+    let chi_square: f64 = counts
+        .iter()
+        .map(|c| (*c as f64 - expected).powi(2) / expected)
+        .sum();
+
+    let mut out = format!(
+        "{} distinct labels over {} of {} boxes, at most {} per box\n",
+        labels.len(),
+        used,
+        boxes,
+        max
+    );
+    out.push_str(&format!(
+        "{} colliding label pairs, chi-square {:.2} ({} degrees of freedom)\n",
+        colliding_pairs,
+        chi_square,
+        boxes - 1
+    ));
+    let mut histogram: HashMap<usize, usize> = HashMap::new();
+    counts
+        .iter()
+        .for_each(|c| *histogram.entry(*c).or_default() += 1);
+    let mut sizes: Vec<_> = histogram.into_iter().collect();
+    sizes.sort();
+    for (size, boxes) in sizes {
+        out.push_str(&format!("{} boxes with {} labels\n", boxes, size));
+    }
+    out
+}
+
+// The initialization sequence language. Whitespace (including newlines) is ignored.
+//
+//     sequence := step ("," step)*
+//     step     := label "=" number    put a lens in the label's box, replacing one of the same label
+//               | label "-"           take the lens with this label out of its box
+//               | label "?"           report which box and slot holds the lens
+//               | "!" number          empty the box with this number
+//               | "*"                 dump the contents of every non-empty box
+//     label    := letter+
+//     number   := digit+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Label(String),
+    Number(u64),
+    Symbol(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut iter = input.char_indices().peekable();
+    while let Some(&(pos, c)) = iter.peek() {
+        if c.is_whitespace() {
+            iter.next();
+        } else if c.is_ascii_alphabetic() {
+            let mut label = String::new();
+            while let Some((_, c)) = iter.next_if(|(_, c)| c.is_ascii_alphabetic()) {
+                label.push(c);
+            }
+            tokens.push((pos, Token::Label(label)));
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some((_, c)) = iter.next_if(|(_, c)| c.is_ascii_digit()) {
+                number.push(c);
+            }
+            let number = number
+                .parse()
+                .map_err(|e| format!("bad number at {}: {}", pos, e))?;
+            tokens.push((pos, Token::Number(number)));
+        } else if "=-?!*,".contains(c) {
+            iter.next();
+            tokens.push((pos, Token::Symbol(c)));
+        } else {
+            return Err(format!("unexpected character {:?} at {}", c, pos));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, PartialEq)]
+enum Op {
+    Add(String, u64),
+    Remove(String),
+    Lookup(String),
+    Clear(usize),
+    Dump,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Add(label, focal_length) => write!(f, "{}={}", label, focal_length),
+            Op::Remove(label) => write!(f, "{}-", label),
+            Op::Lookup(label) => write!(f, "{}?", label),
+            Op::Clear(idx) => write!(f, "!{}", idx),
+            Op::Dump => write!(f, "*"),
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Op>, String> {
+    let tokens = tokenize(input)?;
+    let mut iter = tokens.into_iter().peekable();
+    let mut ops = Vec::new();
+    let unexpected = |t: Option<(usize, Token)>| match t {
+        Some((pos, t)) => format!("unexpected {:?} at {}", t, pos),
+        None => "unexpected end of input".to_string(),
+    };
+    loop {
+        let op = match iter.next() {
+            Some((_, Token::Label(label))) => match iter.next() {
+                Some((_, Token::Symbol('='))) => match iter.next() {
+                    Some((_, Token::Number(n))) => Op::Add(label, n),
+                    t => return Err(unexpected(t)),
+                },
+                Some((_, Token::Symbol('-'))) => Op::Remove(label),
+                Some((_, Token::Symbol('?'))) => Op::Lookup(label),
+                t => return Err(unexpected(t)),
+            },
+            Some((_, Token::Symbol('!'))) => match iter.next() {
+                Some((_, Token::Number(n))) => Op::Clear(n as usize),
+                t => return Err(unexpected(t)),
+            },
+            Some((_, Token::Symbol('*'))) => Op::Dump,
+            t => return Err(unexpected(t)),
+        };
+        ops.push(op);
+        match iter.next() {
+            None => break,
+            Some((_, Token::Symbol(','))) => {}
+            t => return Err(unexpected(t)),
+        }
+    }
+    Ok(ops)
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        panic!("./exe <file> [--trace] [--stats] [--multiplier <n>] [--modulus <n>] [--boxes <n>]");
+    }
+    let mut trace = false;
+    let mut stats = false;
+    let mut hasher = Hasher::default();
+    let mut boxes = 256;
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--trace" => trace = true,
+            "--stats" => stats = true,
+            "--multiplier" => hasher.multiplier = iter.next().unwrap().parse().unwrap(),
+            "--modulus" => hasher.modulus = iter.next().unwrap().parse().unwrap(),
+            "--boxes" => boxes = iter.next().unwrap().parse().unwrap(),
+            _ => panic!("unknown option {}", arg),
+        }
     }
     let content = read_to_string(&args[1]).unwrap();
-    let input = content.lines().next().unwrap();
+    let input: String = content.chars().filter(|c| *c != '\n').collect();
     let mut result = 0;
     input.split(',').for_each(|str| {
-        result += hash(str);
+        result += hasher.hash(str);
     });
     println!("{}", result);
 
-    let mut boxes: HashMap<usize, Box> = HashMap::new();
-    input.split(',').for_each(|str| {
-        let (label, focal_length) = str.split_once(|c| c == '-' || c == '=').unwrap();
-        let hash_value = hash(label);
-        let boks = boxes.entry(hash_value).or_insert(Box::new());
-        if str.contains("-") {
-            boks.remove(label);
-            if boks.lens.len() == 0 {
-                boxes.remove(&hash_value);
-            }
-        } else {
-            boks.add(label, focal_length.parse().unwrap());
+    let ops = match parse(&input) {
+        Ok(ops) => ops,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+    let mut library = LensLibrary::new(hasher, boxes);
+    ops.iter().for_each(|op| {
+        if let Some(out) = library.execute(op) {
+            print!("{}", out);
+        }
+        if trace {
+            println!("After \"{}\":\n{}", op, library.describe());
         }
     });
-    //dbg!(&boxes);
-    let focusing_power = boxes.iter().fold(0, |acc, (box_idx, boks)| {
-        acc + boks.lens.iter().enumerate().fold(0, |acc, (len_idx, len)| {
-            acc + (box_idx + 1) * (len_idx + 1) * len.focal_length as usize
-        })
-    });
-    println!("{}", focusing_power);
-}
+    println!("{}", library.focusing_power());
 
-fn hash(str: &str) -> usize {
-    str.chars().fold(0, |acc, c| (acc + c as usize) * 17 % 256)
+    if stats {
+        print!("{}", hash_stats(&library, &ops));
+    }
 }
@@ -1,16 +1,8 @@
-use std::cmp::max;
-use std::collections::HashMap;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::fs::read_to_string;
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Tile {
-    Ground,
-    HSplit,
-    VSplit,
-    ForwardMirror,
-    BackwardMirror,
-}
+use std::fs::{read_to_string, write};
+use std::thread;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 enum Direction {
@@ -20,11 +12,118 @@ enum Direction {
     Right,
 }
 
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn new(c: char) -> Self {
+        match c {
+            'U' => Direction::Up,
+            'D' => Direction::Down,
+            'L' => Direction::Left,
+            'R' => Direction::Right,
+            _ => panic!("unknown direction {}", c),
+        }
+    }
+}
+
+// How one kind of tile bends a beam: for each direction a beam travels in when it enters the tile,
+// the directions it leaves in. No direction at all absorbs the beam. A portal tile sends the beam
+// out of its twin, the other tile with the same glyph, instead of itself.
+#[derive(Debug, Clone, Default)]
+struct Optic {
+    outputs: HashMap<Direction, Vec<Direction>>,
+    portal: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Optics(HashMap<char, Optic>);
+
+impl Optics {
+    // The puzzle's ground, splitters and mirrors.
+    fn new() -> Self {
+        let mut optics = Optics(HashMap::new());
+        optics.load(
+            "\
+. * *
+| U U
+| D D
+| L UD
+| R UD
+- L L
+- R R
+- U LR
+- D LR
+/ U R
+/ R U
+/ D L
+/ L D
+\\ U L
+\\ L U
+\\ D R
+\\ R D
+",
+        );
+        optics
+    }
+
+    // Adds rules from an optics table. Each line is `<glyph> <in> <out>`, where `in` is one of
+    // U, D, L, R or `*` for every direction, and `out` is a string of those letters, `*` to keep
+    // going straight, or `-` to absorb the beam. `<glyph> portal` marks a glyph as a portal, which
+    // keeps beams going straight unless rules say otherwise. Later rules override earlier ones,
+    // and blank lines and `#` comments are ignored.
+    //
+    //     > * -      one-way prism: beams only pass left to right
+    //     > R R
+    //     X portal   two X tiles in the grid pass beams on to each other
+    fn load(&mut self, table: &str) {
+        for line in table.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let glyph = fields[0].chars().next().unwrap();
+            let optic = self.0.entry(glyph).or_default();
+            match fields[1..] {
+                ["portal"] => {
+                    optic.portal = true;
+                    for d in Direction::ALL {
+                        optic.outputs.entry(d).or_insert_with(|| vec![d]);
+                    }
+                }
+                [incoming, out] => {
+                    let incoming: Vec<Direction> = match incoming {
+                        "*" => Direction::ALL.to_vec(),
+                        _ => incoming.chars().map(Direction::new).collect(),
+                    };
+                    for d in incoming {
+                        let outputs = match out {
+                            "-" => vec![],
+                            "*" => vec![d],
+                            _ => out.chars().map(Direction::new).collect(),
+                        };
+                        optic.outputs.insert(d, outputs);
+                    }
+                }
+                _ => panic!("invalid optics rule: {}", line),
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Grid {
-    tiles: Vec<Tile>,
+    tiles: Vec<char>,
     width: usize,
     height: usize,
+    optics: Optics,
+    // Portal tile -> its twin.
+    portals: HashMap<(usize, usize), (usize, usize)>,
     tile_map: HashMap<Beam, ()>,
 }
 
@@ -36,238 +135,519 @@ struct Beam {
 }
 
 impl Grid {
-    fn new(content: &str) -> Self {
+    fn new(content: &str, optics: Optics) -> Self {
         let mut width = 0;
         let mut height = 0;
-        let mut tiles: Vec<Tile> = Vec::new();
+        let mut tiles: Vec<char> = Vec::new();
         content.lines().for_each(|line| {
             height += 1;
             let mut c_num = 0;
             line.chars().for_each(|c| {
-                tiles.push(match c {
-                    '.' => Tile::Ground,
-                    '|' => Tile::VSplit,
-                    '-' => Tile::HSplit,
-                    '/' => Tile::ForwardMirror,
-                    '\\' => Tile::BackwardMirror,
-                    _ => unreachable!(),
-                });
+                assert!(optics.0.contains_key(&c), "no optics rule for {:?}", c);
+                tiles.push(c);
                 c_num += 1;
             });
             width = c_num;
         });
 
+        let mut twins: HashMap<char, Vec<(usize, usize)>> = HashMap::new();
+        for (i, c) in tiles.iter().enumerate() {
+            if optics.0[c].portal {
+                twins.entry(*c).or_default().push((i / width, i % width));
+            }
+        }
+        let mut portals = HashMap::new();
+        for (c, pair) in twins {
+            assert!(pair.len() == 2, "portal {:?} must appear exactly twice", c);
+            portals.insert(pair[0], pair[1]);
+            portals.insert(pair[1], pair[0]);
+        }
+
         Grid {
             tiles,
             width,
             height,
+            optics,
+            portals,
             tile_map: HashMap::new(),
         }
     }
 
-    fn get_tile(&self, x: usize, y: usize) -> Option<&Tile> {
-        self.tiles.get(x * self.height + y)
+    fn get_tile(&self, x: usize, y: usize) -> Option<&char> {
+        self.tiles.get(x * self.width + y)
     }
 
     fn is_tile_visited(&self, beam: Beam) -> bool {
-        self.tile_map.get(&beam).is_some()
+        self.tile_map.contains_key(&beam)
     }
 
-    fn step(&mut self, beam: Beam) -> Option<(Beam, Option<Beam>)> {
+    fn step(&mut self, beam: Beam) -> Vec<Beam> {
         if self.is_tile_visited(beam) {
-            return None;
+            return vec![];
         }
 
         self.tile_map.insert(beam, ());
+        self.advance(beam)
+    }
 
-        match (self.get_tile(beam.x, beam.y).unwrap(), beam.direction) {
-            (Tile::Ground | Tile::VSplit, Direction::Up)
-            | (Tile::ForwardMirror, Direction::Right)
-            | (Tile::BackwardMirror, Direction::Left) => {
-                if beam.x <= 0 {
-                    None
-                } else {
-                    Some((
-                        Beam {
-                            direction: Direction::Up,
-                            x: beam.x - 1,
-                            ..beam
-                        },
-                        None,
-                    ))
-                }
+    fn outputs(&self, beam: Beam) -> &[Direction] {
+        let optic = &self.optics.0[self.get_tile(beam.x, beam.y).unwrap()];
+        optic
+            .outputs
+            .get(&beam.direction)
+            .map_or(&[], |v| v.as_slice())
+    }
+
+    // Where a beam entering its tile goes next. Beams leaving the grid are dropped.
+    fn advance(&self, beam: Beam) -> Vec<Beam> {
+        let (x, y) = *self
+            .portals
+            .get(&(beam.x, beam.y))
+            .unwrap_or(&(beam.x, beam.y));
+        self.outputs(beam)
+            .iter()
+            .filter_map(|direction| {
+                let (x, y) = match direction {
+                    Direction::Up => (x.checked_sub(1)?, y),
+                    Direction::Down => (x + 1, y),
+                    Direction::Left => (x, y.checked_sub(1)?),
+                    Direction::Right => (x, y + 1),
+                };
+                (x < self.height && y < self.width).then_some(Beam {
+                    direction: *direction,
+                    x,
+                    y,
+                })
+            })
+            .collect()
+    }
+
+    fn run_from(&mut self, beam: Beam) -> usize {
+        let mut wl = vec![beam];
+        let mut energised = Bitset::new(self.width * self.height);
+
+        while !wl.is_empty() {
+            wl.iter()
+                .for_each(|beam| energised.set(beam.x * self.width + beam.y));
+            wl = wl.into_iter().flat_map(|beam| self.step(beam)).collect();
+        }
+        energised.count()
+    }
+
+    // The closed beam paths among the states visited by the last run, each as its sorted tiles.
+    // A loop is a strongly connected set of beam states, or a state that leads straight back to
+    // itself.
+    fn loops(&self) -> Vec<Vec<(usize, usize)>> {
+        let states: Vec<Beam> = self.tile_map.keys().copied().collect();
+        let ids: HashMap<Beam, usize> = states.iter().enumerate().map(|(i, b)| (*b, i)).collect();
+        let edges: Vec<Vec<usize>> = states
+            .iter()
+            .map(|b| self.advance(*b).iter().map(|n| ids[n]).collect())
+            .collect();
+        tarjan(&edges)
+            .into_iter()
+            .filter(|c| c.len() > 1 || edges[c[0]].contains(&c[0]))
+            .map(|c| {
+                let tiles: HashSet<(usize, usize)> =
+                    c.iter().map(|i| (states[*i].x, states[*i].y)).collect();
+                let mut tiles: Vec<_> = tiles.into_iter().collect();
+                tiles.sort();
+                tiles
+            })
+            .collect()
+    }
+
+    // Every beam entering the grid from its border.
+    fn entries(&self) -> Vec<Beam> {
+        let mut entries = Vec::new();
+        for x in 0..self.height {
+            entries.push(Beam {
+                direction: Direction::Right,
+                x,
+                y: 0,
+            });
+            entries.push(Beam {
+                direction: Direction::Left,
+                x,
+                y: self.width - 1,
+            });
+        }
+        for y in 0..self.width {
+            entries.push(Beam {
+                direction: Direction::Down,
+                x: 0,
+                y,
+            });
+            entries.push(Beam {
+                direction: Direction::Up,
+                x: self.height - 1,
+                y,
+            });
+        }
+        entries
+    }
+
+    // Beams that fan out into several beams are the nodes of the beam graph.
+    fn splits(&self, beam: Beam) -> bool {
+        self.outputs(beam).len() > 1
+    }
+
+    // Follows a beam until it leaves the grid, is absorbed or fans out. Returns the tiles it
+    // crossed (the fan-out tile included) and the beam that fans out.
+    fn segment(&self, mut beam: Beam) -> (Bitset, Option<Beam>) {
+        let mut tiles = Bitset::new(self.width * self.height);
+        let mut seen: HashSet<Beam> = HashSet::new();
+        loop {
+            tiles.set(beam.x * self.width + beam.y);
+            if self.splits(beam) {
+                return (tiles, Some(beam));
             }
-            (Tile::Ground | Tile::VSplit, Direction::Down)
-            | (Tile::ForwardMirror, Direction::Left)
-            | (Tile::BackwardMirror, Direction::Right) => {
-                if beam.x >= self.height - 1 {
-                    None
-                } else {
-                    Some((
-                        Beam {
-                            direction: Direction::Down,
-                            x: beam.x + 1,
-                            ..beam
-                        },
-                        None,
-                    ))
-                }
+            if !seen.insert(beam) {
+                return (tiles, None);
             }
-            (Tile::Ground | Tile::HSplit, Direction::Left)
-            | (Tile::ForwardMirror, Direction::Down)
-            | (Tile::BackwardMirror, Direction::Up) => {
-                if beam.y <= 0 {
-                    None
-                } else {
-                    Some((
-                        Beam {
-                            direction: Direction::Left,
-                            y: beam.y - 1,
-                            ..beam
-                        },
-                        None,
-                    ))
-                }
+            match self.advance(beam)[..] {
+                [next] => beam = next,
+                _ => return (tiles, None),
             }
-            (Tile::Ground | Tile::HSplit, Direction::Right)
-            | (Tile::ForwardMirror, Direction::Up)
-            | (Tile::BackwardMirror, Direction::Down) => {
-                if beam.y >= self.width - 1 {
-                    None
-                } else {
-                    Some((
-                        Beam {
-                            direction: Direction::Right,
-                            y: beam.y + 1,
-                            ..beam
-                        },
-                        None,
-                    ))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bitset(Vec<u64>);
+
+impl Bitset {
+    fn new(len: usize) -> Self {
+        Bitset(vec![0; len.div_ceil(64)])
+    }
+
+    fn set(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn union(&mut self, other: &Bitset) {
+        self.0.iter_mut().zip(&other.0).for_each(|(a, b)| *a |= b);
+    }
+
+    fn count(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+// The beams that fan out form a graph: each sends beams along segments, and every segment ends at
+// another fan-out beam or leaves the grid. Every node in a strongly connected component energises
+// the same tiles, so each component's tiles are computed once, from the components it leads to.
+struct BeamGraph {
+    // Fan-out beam -> component index.
+    component: HashMap<Beam, usize>,
+    energised: Vec<Bitset>,
+}
+
+impl BeamGraph {
+    fn new(grid: &Grid) -> Self {
+        let mut nodes: Vec<Beam> = Vec::new();
+        for x in 0..grid.height {
+            for y in 0..grid.width {
+                for direction in Direction::ALL {
+                    let beam = Beam { direction, x, y };
+                    if grid.splits(beam) {
+                        nodes.push(beam);
+                    }
                 }
             }
-            (Tile::HSplit, Direction::Up | Direction::Down) => {
-                let mut beam1 = None;
-                let mut beam2 = None;
-                if beam.y < self.width - 1 {
-                    beam1 = Some(Beam {
-                        direction: Direction::Right,
-                        y: beam.y + 1,
-                        ..beam
-                    });
-                }
-                if beam.y > 0 {
-                    beam2 = Some(Beam {
-                        direction: Direction::Left,
-                        y: beam.y - 1,
-                        ..beam
-                    });
+        }
+        let ids: HashMap<Beam, usize> = nodes.iter().enumerate().map(|(i, b)| (*b, i)).collect();
+        let mut tiles: Vec<Bitset> = Vec::new();
+        let mut edges: Vec<Vec<usize>> = Vec::new();
+        for node in &nodes {
+            let mut own = Bitset::new(grid.width * grid.height);
+            own.set(node.x * grid.width + node.y);
+            let mut next = Vec::new();
+            for beam in grid.advance(*node) {
+                let (segment, end) = grid.segment(beam);
+                own.union(&segment);
+                if let Some(end) = end {
+                    next.push(ids[&end]);
                 }
-                Self::return_beams(beam1, beam2)
             }
-            (Tile::VSplit, Direction::Left | Direction::Right) => {
-                let mut beam1 = None;
-                let mut beam2 = None;
-                if beam.x < self.height - 1 {
-                    beam1 = Some(Beam {
-                        direction: Direction::Down,
-                        x: beam.x + 1,
-                        ..beam
-                    });
+            tiles.push(own);
+            edges.push(next);
+        }
+
+        // Components come sinks first, so the components a new one leads to are already done.
+        let mut component = vec![0; nodes.len()];
+        let mut energised: Vec<Bitset> = Vec::new();
+        for (c, members) in tarjan(&edges).into_iter().enumerate() {
+            members.iter().for_each(|v| component[*v] = c);
+            let mut union = Bitset::new(grid.width * grid.height);
+            for &v in &members {
+                union.union(&tiles[v]);
+                for &w in &edges[v] {
+                    if component[w] != c {
+                        union.union(&energised[component[w]]);
+                    }
                 }
-                if beam.x > 0 {
-                    beam2 = Some(Beam {
-                        direction: Direction::Up,
-                        x: beam.x - 1,
-                        ..beam
-                    });
+            }
+            energised.push(union);
+        }
+        BeamGraph {
+            component: nodes.into_iter().zip(component).collect(),
+            energised,
+        }
+    }
+
+    fn energy(&self, grid: &Grid, entry: Beam) -> usize {
+        let (mut tiles, end) = grid.segment(entry);
+        if let Some(end) = end {
+            tiles.union(&self.energised[self.component[&end]]);
+        }
+        tiles.count()
+    }
+}
+
+// Tarjan's algorithm. Returns the strongly connected components in the order they complete, which
+// puts every component after all components reachable from it.
+fn tarjan(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next_index: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(s: &mut State, v: usize) {
+        s.index[v] = Some(s.next_index);
+        s.low[v] = s.next_index;
+        s.next_index += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+        for &w in &s.edges[v] {
+            match s.index[w] {
+                None => {
+                    visit(s, w);
+                    s.low[v] = min(s.low[v], s.low[w]);
                 }
-                Self::return_beams(beam1, beam2)
+                Some(i) if s.on_stack[w] => s.low[v] = min(s.low[v], i),
+                _ => {}
+            }
+        }
+        if Some(s.low[v]) != s.index[v] {
+            return;
+        }
+        let mut members = Vec::new();
+        loop {
+            let w = s.stack.pop().unwrap();
+            s.on_stack[w] = false;
+            members.push(w);
+            if w == v {
+                break;
             }
         }
+        s.components.push(members);
     }
 
-    fn return_beams(beam1: Option<Beam>, beam2: Option<Beam>) -> Option<(Beam, Option<Beam>)> {
-        match (beam1, beam2) {
-            (Some(beam1), Some(beam2)) => Some((beam1, Some(beam2))),
-            (Some(beam1), None) => Some((beam1, None)),
-            (None, Some(beam2)) => Some((beam2, None)),
-            (None, None) => None,
+    let n = edges.len();
+    let mut s = State {
+        edges,
+        index: vec![None; n],
+        low: vec![0; n],
+        on_stack: vec![false; n],
+        stack: Vec::new(),
+        next_index: 0,
+        components: Vec::new(),
+    };
+    for v in 0..n {
+        if s.index[v].is_none() {
+            visit(&mut s, v);
         }
     }
+    s.components
+}
 
-    fn run_from(&mut self, beam: Beam) -> usize {
-        let mut wl = vec![beam];
+// Heat ramp from black through red and yellow to white, for `t` in [0, 1].
+fn heat(t: f64) -> [u8; 3] {
+    let channel = |start: f64| ((t * 3.0 - start).clamp(0.0, 1.0) * 255.0) as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+// A grid of heat values in [0, 1], with the character that stands for each cell in text output.
+// Cells without a value are drawn as their character only.
+struct Heatmap {
+    rows: Vec<Vec<(Option<f64>, char)>>,
+}
+
+impl Heatmap {
+    // How many distinct beam directions crossed each tile in the last run, `#` if any did.
+    fn energised(grid: &Grid) -> Self {
+        let mut counts = vec![vec![0; grid.width]; grid.height];
+        grid.tile_map.keys().for_each(|b| counts[b.x][b.y] += 1);
+        let rows = counts
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|n| (Some(n as f64 / 4.0), if n > 0 { '#' } else { '.' }))
+                    .collect()
+            })
+            .collect();
+        Heatmap { rows }
+    }
+
+    // The grid framed by the energy of the beam entering next to each border tile, shown as a
+    // digit from 0 to 9 relative to the strongest entry.
+    fn border(grid: &Grid, energies: &HashMap<Beam, usize>) -> Self {
+        let max = *energies.values().max().unwrap_or(&1) as f64;
+        let entry = |direction, x, y| {
+            let e = energies[&Beam { direction, x, y }] as f64 / max;
+            (
+                Some(e),
+                char::from_digit((e * 9.0).round() as u32, 10).unwrap(),
+            )
+        };
+        let (h, w) = (grid.height, grid.width);
+        let mut rows = vec![vec![(None, ' '); w + 2]; h + 2];
+        for x in 0..h {
+            rows[x + 1][0] = entry(Direction::Right, x, 0);
+            rows[x + 1][w + 1] = entry(Direction::Left, x, w - 1);
+            for y in 0..w {
+                rows[x + 1][y + 1] = (None, *grid.get_tile(x, y).unwrap());
+            }
+        }
+        for y in 0..w {
+            rows[0][y + 1] = entry(Direction::Down, 0, y);
+            rows[h + 1][y + 1] = entry(Direction::Up, h - 1, y);
+        }
+        Heatmap { rows }
+    }
 
-        while wl.len() != 0 {
-            wl = wl
-                .into_iter()
-                .flat_map(|beam| {
-                    let res = self.step(beam);
-                    if let Some(res) = res {
-                        let beam1 = res.0;
-                        if let Some(beam2) = res.1 {
-                            vec![beam1, beam2]
-                        } else {
-                            vec![beam1]
-                        }
-                    } else {
-                        vec![]
+    fn ascii(&self) -> String {
+        let mut out = String::new();
+        for row in &self.rows {
+            row.iter().for_each(|(_, c)| out.push(*c));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn ansi(&self) -> String {
+        let mut out = String::new();
+        for row in &self.rows {
+            for (value, c) in row {
+                match value {
+                    Some(t) => {
+                        let [r, g, b] = heat(*t);
+                        // Dark text on the bright end of the ramp so the character stays readable.
+                        let fg = if *t > 0.5 { 30 } else { 97 };
+                        out.push_str(&format!("\x1b[{};48;2;{};{};{}m{}\x1b[0m", fg, r, g, b, c));
                     }
-                })
-                .collect();
+                    None => out.push(*c),
+                }
+            }
+            out.push('\n');
         }
-        let mut m = HashMap::new();
-        self.tile_map.keys().for_each(|k| {
-            m.insert((k.x, k.y), ());
-        });
-        return m.len();
+        out
+    }
+
+    // A binary PPM with `scale` pixels per cell. Cells without a value are grey.
+    fn ppm(&self, scale: usize) -> Vec<u8> {
+        let (h, w) = (self.rows.len(), self.rows[0].len());
+        let mut data = format!("P6\n{} {}\n255\n", w * scale, h * scale).into_bytes();
+        for x in 0..h * scale {
+            for y in 0..w * scale {
+                let rgb = match self.rows[x / scale][y / scale].0 {
+                    Some(t) => heat(t),
+                    None => [96, 96, 96],
+                };
+                data.extend_from_slice(&rgb);
+            }
+        }
+        data
     }
 
-    fn clear_tile_map(&mut self) {
-        self.tile_map.clear();
+    fn export(&self, format: &str, out: &str) {
+        match format {
+            "ascii" => print!("{}", self.ascii()),
+            "ansi" => print!("{}", self.ansi()),
+            "ppm" => write(out, self.ppm(4)).unwrap(),
+            _ => panic!("unknown heatmap format {}", format),
+        }
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        panic!(
+            "./exe <file> [--optics <file>] [--loops] [--heatmap ascii|ansi|ppm] \
+             [--border ascii|ansi|ppm] [--out <file>]"
+        );
+    }
+    let mut optics = Optics::new();
+    let mut show_loops = false;
+    let mut heatmap: Option<String> = None;
+    let mut border: Option<String> = None;
+    let mut out: Option<String> = None;
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--optics" => optics.load(&read_to_string(iter.next().unwrap()).unwrap()),
+            "--loops" => show_loops = true,
+            "--heatmap" => heatmap = iter.next().cloned(),
+            "--border" => border = iter.next().cloned(),
+            "--out" => out = iter.next().cloned(),
+            _ => panic!("unknown option {}", arg),
+        }
     }
     let content = read_to_string(&args[1]).unwrap();
-    let mut grid = Grid::new(&content);
+    let mut grid = Grid::new(&content, optics);
 
     let energy = grid.run_from(Beam {
         direction: Direction::Right,
         x: 0,
         y: 0,
     });
+    let loops = grid.loops();
 
-    let mut max_energy = 0;
-    for x in 0..grid.height {
-        for y in 0..grid.width {
-            if x != 0 && x != grid.height - 1 && y != 0 && y != grid.width - 1 {
-                continue;
-            }
-            let mut directions = vec![];
-            if x == 0 {
-                directions.push(Direction::Down);
-            }
-            if x == grid.height - 1 {
-                directions.push(Direction::Up);
-            }
-            if y == 0 {
-                directions.push(Direction::Right);
-            }
-            if y == grid.width - 1 {
-                directions.push(Direction::Left);
-            }
-            for direction in directions {
-                let energy = grid.run_from(Beam { direction, x, y });
-                grid.clear_tile_map();
-                max_energy = max(max_energy, energy);
-            }
-        }
-    }
+    let graph = BeamGraph::new(&grid);
+    let entries = grid.entries();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = entries.len().div_ceil(threads).max(1);
+    let energies: Vec<usize> = thread::scope(|s| {
+        let handles: Vec<_> = entries
+            .chunks(chunk)
+            .map(|chunk| {
+                let (grid, graph) = (&grid, &graph);
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|entry| graph.energy(grid, *entry))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+    let max_energy = energies.iter().max().copied().unwrap_or(0);
     println!("{}", energy);
     println!("{}", max_energy);
+
+    if show_loops {
+        for tiles in loops {
+            println!("beam loop over {} tiles: {:?}", tiles.len(), tiles);
+        }
+    }
+    if let Some(format) = heatmap {
+        let out = out.as_deref().unwrap_or("heatmap.ppm");
+        Heatmap::energised(&grid).export(&format, out);
+    }
+    if let Some(format) = border {
+        let energies: HashMap<Beam, usize> = entries.into_iter().zip(energies).collect();
+        let out = out.as_deref().unwrap_or("border.ppm");
+        Heatmap::border(&grid, &energies).export(&format, out);
+    }
 }
@@ -1,13 +1,21 @@
+use priority_queue::PriorityQueue;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::env;
 use std::fs::read_to_string;
 
-// Part1
-// const MAX_STEP: usize = 3;
-// const MIN_STEP: usize = 1;
-// Part2
-const MAX_STEP: usize = 10;
-const MIN_STEP: usize = 4;
+// How far a crucible may go in a straight line: it has to move at least `min` blocks before it can
+// turn or stop, and at most `max` blocks before it has to turn.
+#[derive(Debug, Copy, Clone)]
+struct Crucible {
+    min: usize,
+    max: usize,
+}
+
+// Part 1
+const CRUCIBLE: Crucible = Crucible { min: 1, max: 3 };
+// Part 2
+const ULTRA_CRUCIBLE: Crucible = Crucible { min: 4, max: 10 };
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum SubState {
@@ -17,6 +25,27 @@ enum SubState {
     Bottom(usize),
 }
 
+impl SubState {
+    fn step(self) -> usize {
+        match self {
+            SubState::Left(step)
+            | SubState::Right(step)
+            | SubState::Top(step)
+            | SubState::Bottom(step) => step,
+        }
+    }
+
+    // The arrow the puzzle text draws for a move in this direction.
+    fn glyph(self) -> char {
+        match self {
+            SubState::Left(_) => '>',
+            SubState::Right(_) => '<',
+            SubState::Top(_) => 'v',
+            SubState::Bottom(_) => '^',
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 struct CellState {
     x: usize,
@@ -25,90 +54,62 @@ struct CellState {
 }
 
 impl CellState {
-    fn next_states(&self, width: usize, height: usize) -> Vec<CellState> {
+    // Whether a crucible in this state may stop here: a run of 0 only happens at the start.
+    fn can_stop(&self, crucible: Crucible) -> bool {
+        let step = self.substate.step();
+        step == 0 || step >= crucible.min
+    }
+
+    // The states reachable in one move: straight on while the run is below `crucible.max`, and a
+    // quarter turn either way once it has reached `crucible.min`. Moves that would leave the
+    // `width` x `height` grid are not generated.
+    fn next_states(&self, width: usize, height: usize, crucible: Crucible) -> Vec<CellState> {
+        let step = self.substate.step();
         let mut l = vec![];
-        match self.substate {
-            SubState::Left(step) | SubState::Right(step) => {
-                if step < MAX_STEP {
-                    let y;
-                    let substate;
-                    if let SubState::Left(_) = self.substate {
-                        y = self.y.saturating_add(1);
-                        substate = SubState::Left(step + 1);
-                    } else {
-                        y = self.y.saturating_sub(1);
-                        substate = SubState::Right(step + 1);
-                    }
-                    l.push(CellState {
-                        x: self.x,
-                        y,
-                        substate,
-                    });
-                }
-                if step >= MIN_STEP {
-                    l.extend([
-                        CellState {
-                            x: self.x.saturating_add(1),
-                            y: self.y,
-                            substate: SubState::Top(1),
-                        },
-                        CellState {
-                            x: self.x.saturating_sub(1),
-                            y: self.y,
-                            substate: SubState::Bottom(1),
-                        },
-                    ]);
-                }
-            }
-            SubState::Top(step) | SubState::Bottom(step) => {
-                if step < MAX_STEP {
-                    let x;
-                    let substate;
-                    if let SubState::Top(_) = self.substate {
-                        x = self.x.saturating_add(1);
-                        substate = SubState::Top(step + 1);
-                    } else {
-                        x = self.x.saturating_sub(1);
-                        substate = SubState::Bottom(step + 1);
-                    }
-                    l.push(CellState {
-                        x,
-                        y: self.y,
-                        substate,
-                    });
+        if step < crucible.max {
+            l.push(match self.substate {
+                SubState::Left(_) => SubState::Left(step + 1),
+                SubState::Right(_) => SubState::Right(step + 1),
+                SubState::Top(_) => SubState::Top(step + 1),
+                SubState::Bottom(_) => SubState::Bottom(step + 1),
+            });
+        }
+        if step >= crucible.min {
+            match self.substate {
+                SubState::Left(_) | SubState::Right(_) => {
+                    l.extend([SubState::Top(1), SubState::Bottom(1)])
                 }
-                if step >= MIN_STEP {
-                    l.extend([
-                        CellState {
-                            x: self.x,
-                            y: self.y.saturating_add(1),
-                            substate: SubState::Left(1),
-                        },
-                        CellState {
-                            x: self.x,
-                            y: self.y.saturating_sub(1),
-                            substate: SubState::Right(1),
-                        },
-                    ]);
+                SubState::Top(_) | SubState::Bottom(_) => {
+                    l.extend([SubState::Left(1), SubState::Right(1)])
                 }
             }
         }
-
-        // Filter out invalid states
         l.into_iter()
-            .filter(|state| {
-                (state.x, state.y) != (self.x, self.y) && state.x < height && state.y < width
-            })
+            .filter_map(|substate| self.moved(substate, width, height))
             .collect()
     }
+
+    // The state after moving one block the way `substate` goes, or None if that leaves the grid.
+    fn moved(&self, substate: SubState, width: usize, height: usize) -> Option<CellState> {
+        let (x, y) = match substate {
+            SubState::Left(_) => (self.x, self.y + 1),
+            SubState::Right(_) => (self.x, self.y.checked_sub(1)?),
+            SubState::Top(_) => (self.x + 1, self.y),
+            SubState::Bottom(_) => (self.x.checked_sub(1)?, self.y),
+        };
+        (x < height && y < width).then_some(CellState { x, y, substate })
+    }
 }
 
 struct Space {
     dists: Vec<u64>,
-    cell_states: HashMap<CellState, u64>,
     width: usize,
     height: usize,
+    // Best known heat loss of every state reached by the last search.
+    cell_states: HashMap<CellState, u64>,
     prev: HashMap<CellState, CellState>,
+    // The state the last search reached the goal in.
+    end: Option<CellState>,
 }
 
 impl Space {
@@ -116,57 +117,18 @@ impl Space {
         let mut height = 0;
         let mut width = 0;
         let mut dists = Vec::new();
-        let mut cell_states = HashMap::new();
-        content.lines().enumerate().for_each(|(x, line)| {
+        content.lines().for_each(|line| {
             height += 1;
-            let mut n = 0;
-            line.chars().enumerate().for_each(|(y, c)| {
-                let v = if (x, y) == (0, 0) { 0 } else { u64::MAX };
-                n += 1;
-                dists.push(c as u64 - 48);
-                for i in 1..=MAX_STEP {
-                    cell_states.insert(
-                        CellState {
-                            x,
-                            y,
-                            substate: SubState::Left(i),
-                        },
-                        v,
-                    );
-                    cell_states.insert(
-                        CellState {
-                            x,
-                            y,
-                            substate: SubState::Right(i),
-                        },
-                        v,
-                    );
-                    cell_states.insert(
-                        CellState {
-                            x,
-                            y,
-                            substate: SubState::Top(i),
-                        },
-                        v,
-                    );
-                    cell_states.insert(
-                        CellState {
-                            x,
-                            y,
-                            substate: SubState::Bottom(i),
-                        },
-                        v,
-                    );
-                }
-            });
-            width = n;
+            width = line.len();
+            line.chars().for_each(|c| dists.push(c as u64 - 48));
         });
         Self {
             dists,
             width,
             height,
-            cell_states,
+            cell_states: HashMap::new(),
             prev: HashMap::new(),
+            end: None,
         }
     }
 
@@ -174,102 +136,372 @@ impl Space {
         self.dists[x * self.width + y]
     }
 
-    fn run_once(&mut self, wl: Vec<CellState>) -> Vec<CellState> {
-        let mut next_wl = Vec::new();
-        for state in wl {
-            let next_states = state.next_states(self.width, self.height);
-            for next_state in next_states {
-                let dist = self.cell_states[&state] + self.get_distance(next_state.x, next_state.y);
-                if dist < self.cell_states[&next_state] {
-                    self.cell_states.insert(next_state, dist);
+    // Dijkstra over (cell, direction, run) states. The crucible leaves `start` in any direction
+    // with a run of 0, which only allows it to go straight, and may only stop at `goal` once it
+    // has run at least `crucible.min` blocks. Returns the least heat loss, or None if the goal
+    // cannot be reached.
+    fn run(
+        &mut self,
+        crucible: Crucible,
+        start: (usize, usize),
+        goal: (usize, usize),
+    ) -> Option<u64> {
+        self.cell_states.clear();
+        self.prev.clear();
+        self.end = None;
+
+        let mut queue = PriorityQueue::new();
+        for substate in [
+            SubState::Left(0),
+            SubState::Right(0),
+            SubState::Top(0),
+            SubState::Bottom(0),
+        ] {
+            let state = CellState {
+                x: start.0,
+                y: start.1,
+                substate,
+            };
+            self.cell_states.insert(state, 0);
+            queue.push(state, Reverse(0));
+        }
+        while let Some((state, Reverse(dist))) = queue.pop() {
+            if (state.x, state.y) == goal && state.can_stop(crucible) {
+                self.end = Some(state);
+                return Some(dist);
+            }
+            for next_state in state.next_states(self.width, self.height, crucible) {
+                let next_dist = dist + self.get_distance(next_state.x, next_state.y);
+                if self
+                    .cell_states
+                    .get(&next_state)
+                    .is_none_or(|d| next_dist < *d)
+                {
+                    self.cell_states.insert(next_state, next_dist);
                     self.prev.insert(next_state, state);
-                    next_wl.push(next_state);
+                    queue.push_increase(next_state, Reverse(next_dist));
                 }
             }
         }
-        next_wl
+        None
     }
 
-    fn run(&mut self) -> u64 {
-        let mut wl = vec![
-            CellState {
-                x: 0,
-                y: 0,
-                substate: SubState::Left(1),
-            },
-            CellState {
-                x: 0,
-                y: 0,
-                substate: SubState::Top(1),
-            },
-        ];
-        loop {
-            wl = self.run_once(wl);
-            if wl.len() == 0 {
-                break;
+    // The path found by the last search, from start to goal.
+    fn trace(&self) -> Option<Route> {
+        let mut p = self.end?;
+        let mut steps = vec![(p, self.cell_states[&p])];
+        while let Some(q) = self.prev.get(&p) {
+            p = *q;
+            steps.push((p, self.cell_states[&p]));
+        }
+        steps.reverse();
+        Some(Route { steps })
+    }
+
+    // The `k` cheapest distinct paths from `start` to `goal`, cheapest first. Every state may be
+    // settled up to `k` times, each time with the next cheapest way of reaching it, so the goal is
+    // settled once for each of the `k` best paths. Queued paths share their prefixes through
+    // `arena`, which holds each state with its heat loss and the index of the state before it.
+    fn k_best(
+        &self,
+        crucible: Crucible,
+        start: (usize, usize),
+        goal: (usize, usize),
+        k: usize,
+    ) -> Vec<Route> {
+        let mut arena: Vec<(CellState, u64, Option<usize>)> = Vec::new();
+        let mut settled: HashMap<CellState, usize> = HashMap::new();
+        let mut queue = PriorityQueue::new();
+        let mut routes = Vec::new();
+        for substate in [
+            SubState::Left(0),
+            SubState::Right(0),
+            SubState::Top(0),
+            SubState::Bottom(0),
+        ] {
+            let state = CellState {
+                x: start.0,
+                y: start.1,
+                substate,
+            };
+            queue.push(arena.len(), Reverse(0));
+            arena.push((state, 0, None));
+        }
+        while let Some((idx, Reverse(dist))) = queue.pop() {
+            let state = arena[idx].0;
+            let times = settled.entry(state).or_default();
+            if *times == k {
+                continue;
+            }
+            *times += 1;
+            // When `start` is the goal, the start states all stand for the same empty path: it is
+            // recorded once, and they all carry on so that round trips are found as well.
+            let empty = state.substate.step() == 0;
+            if (state.x, state.y) == goal
+                && state.can_stop(crucible)
+                && (!empty || routes.is_empty())
+            {
+                let mut steps = Vec::new();
+                let mut at = Some(idx);
+                while let Some(i) = at {
+                    steps.push((arena[i].0, arena[i].1));
+                    at = arena[i].2;
+                }
+                steps.reverse();
+                routes.push(Route { steps });
+                if routes.len() == k {
+                    break;
+                }
+                if !empty {
+                    continue;
+                }
+            }
+            for next_state in state.next_states(self.width, self.height, crucible) {
+                if settled.get(&next_state).is_some_and(|times| *times == k) {
+                    continue;
+                }
+                let next_dist = dist + self.get_distance(next_state.x, next_state.y);
+                queue.push(arena.len(), Reverse(next_dist));
+                arena.push((next_state, next_dist, Some(idx)));
             }
         }
-        self.min_val(self.height - 1, self.width - 1)
+        routes
     }
+}
 
-    fn min_val(&self, x: usize, y: usize) -> u64 {
-        self.cell_states[&self.min_val_state(x, y)]
+// A path through the grid as the states it passes through, each with the heat loss so far.
+struct Route {
+    steps: Vec<(CellState, u64)>,
+}
+
+impl Route {
+    fn loss(&self) -> u64 {
+        self.steps.last().map_or(0, |(_, loss)| *loss)
     }
 
-    fn min_val_state(&self, x: usize, y: usize) -> CellState {
-        let states: Vec<_> = (MIN_STEP..=MAX_STEP)
-            .flat_map(|step| {
-                [
-                    CellState {
-                        x,
-                        y,
-                        substate: SubState::Top(step),
-                    },
-                    CellState {
-                        x,
-                        y,
-                        substate: SubState::Bottom(step),
-                    },
-                    CellState {
-                        x,
-                        y,
-                        substate: SubState::Left(step),
-                    },
-                    CellState {
-                        x,
-                        y,
-                        substate: SubState::Right(step),
-                    },
-                ]
-            })
+    // The grid with every block the path enters replaced by the arrow it was entered with.
+    fn render(&self, space: &Space) -> String {
+        let mut glyphs: Vec<char> = space
+            .dists
+            .iter()
+            .map(|d| char::from_digit(*d as u32, 10).unwrap())
             .collect();
-        let v = states.iter().min_by_key(|v| self.cell_states[v]).unwrap();
-        *v
+        for (state, _) in &self.steps[1..] {
+            glyphs[state.x * space.width + state.y] = state.substate.glyph();
+        }
+        let mut out = String::new();
+        for row in glyphs.chunks(space.width) {
+            out.extend(row);
+            out.push('\n');
+        }
+        out
     }
 
-    fn trace(&self) -> Vec<(usize, usize)> {
-        let mut p = self.min_val_state(self.height - 1, self.width - 1);
-        let mut l = vec![(p.x, p.y)];
-        loop {
-            if (p.x, p.y) == (0, 0) {
-                break;
-            }
-            p = self.prev[&p];
-            l.push((p.x, p.y));
+    // One line per move: the block entered, the direction, how long the straight run is so far and
+    // the total heat loss.
+    fn table(&self) -> String {
+        let mut out = format!(
+            "{:>5} {:>10} {:>3} {:>3} {:>6}\n",
+            "step", "cell", "dir", "run", "loss"
+        );
+        for (i, (state, loss)) in self.steps.iter().enumerate().skip(1) {
+            out.push_str(&format!(
+                "{:>5} {:>10} {:>3} {:>3} {:>6}\n",
+                i,
+                format!("({},{})", state.x, state.y),
+                state.substate.glyph(),
+                state.substate.step(),
+                loss
+            ));
         }
-        l.reverse();
-        l
+        out
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        panic!("./exe <file> [--crucible <min> <max>]... [--start <row> <col>] [--goal <row> <col>] [--trace] [--best <k>]");
     }
     let content = read_to_string(&args[1]).unwrap();
     let mut space = Space::new(&content);
-    let score = space.run();
-    println!("{}", score);
-    //println!("{:?}", space.trace());
+
+    let mut crucibles = Vec::new();
+    let mut start = (0, 0);
+    let mut goal = (space.height - 1, space.width - 1);
+    let mut trace = false;
+    let mut best = None;
+    let mut iter = args[2..].iter();
+    let num =
+        |iter: &mut std::slice::Iter<String>| -> usize { iter.next().unwrap().parse().unwrap() };
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--crucible" => {
+                let (min, max) = (num(&mut iter), num(&mut iter));
+                if min == 0 || min > max {
+                    panic!("invalid crucible limits {} {}", min, max);
+                }
+                crucibles.push(Crucible { min, max });
+            }
+            "--start" => start = (num(&mut iter), num(&mut iter)),
+            "--goal" => goal = (num(&mut iter), num(&mut iter)),
+            "--trace" => trace = true,
+            "--best" => best = Some(num(&mut iter)),
+            _ => panic!("unknown option {}", arg),
+        }
+    }
+    if crucibles.is_empty() {
+        crucibles = vec![CRUCIBLE, ULTRA_CRUCIBLE];
+    }
+    for cell in [start, goal] {
+        if cell.0 >= space.height || cell.1 >= space.width {
+            panic!(
+                "{:?} is outside the {}x{} grid",
+                cell, space.height, space.width
+            );
+        }
+    }
+
+    for crucible in crucibles {
+        match space.run(crucible, start, goal) {
+            Some(score) => println!("{}", score),
+            None => println!("goal {:?} cannot be reached", goal),
+        }
+        if trace {
+            if let Some(route) = space.trace() {
+                print!("{}", route.render(&space));
+                print!("{}", route.table());
+            }
+        }
+        if let Some(k) = best {
+            for (i, route) in space.k_best(crucible, start, goal, k).iter().enumerate() {
+                println!("path {}: heat loss {}", i + 1, route.loss());
+                print!("{}", route.render(&space));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn least_loss(content: &str, crucible: Crucible) -> Option<u64> {
+    let mut space = Space::new(content);
+    let goal = (space.height - 1, space.width - 1);
+    space.run(crucible, (0, 0), goal)
+}
+
+#[test]
+fn test_next_states_stay_in_bounds() {
+    let state = |x, y, substate| CellState { x, y, substate };
+    // Heading up along the top edge: only the turn into the grid is left.
+    assert_eq!(
+        state(0, 0, SubState::Bottom(1)).next_states(5, 3, CRUCIBLE),
+        vec![state(0, 1, SubState::Left(1))]
+    );
+    // Heading down into the bottom right corner.
+    assert_eq!(
+        state(2, 4, SubState::Top(1)).next_states(5, 3, CRUCIBLE),
+        vec![state(2, 3, SubState::Right(1))]
+    );
+    // Too early to turn and nowhere to go straight.
+    assert_eq!(
+        state(2, 4, SubState::Left(2)).next_states(5, 3, ULTRA_CRUCIBLE),
+        vec![]
+    );
+    for x in 0..3 {
+        for y in 0..5 {
+            for substate in [
+                SubState::Left(4),
+                SubState::Right(4),
+                SubState::Top(4),
+                SubState::Bottom(4),
+            ] {
+                for next in state(x, y, substate).next_states(5, 3, ULTRA_CRUCIBLE) {
+                    assert!(next.x < 3 && next.y < 5);
+                    assert_eq!(next.x.abs_diff(x) + next.y.abs_diff(y), 1);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_single_row() {
+    assert_eq!(least_loss("1234", CRUCIBLE), Some(9));
+    // A crucible can't turn in a single row, so the run has to fit the limits exactly.
+    assert_eq!(least_loss("12345", CRUCIBLE), None);
+    assert_eq!(least_loss("1234", ULTRA_CRUCIBLE), None);
+    assert_eq!(least_loss("12345", ULTRA_CRUCIBLE), Some(14));
+    assert_eq!(least_loss("12345678912", ULTRA_CRUCIBLE), Some(47));
+    assert_eq!(least_loss("123456789123", ULTRA_CRUCIBLE), None);
+    assert_eq!(least_loss("7", CRUCIBLE), Some(0));
+}
+
+#[test]
+fn test_single_column() {
+    assert_eq!(least_loss("1\n2\n3\n4", CRUCIBLE), Some(9));
+    assert_eq!(least_loss("1\n2\n3\n4\n5", CRUCIBLE), None);
+    assert_eq!(least_loss("1\n2\n3\n4\n5", ULTRA_CRUCIBLE), Some(14));
+}
+
+#[test]
+fn test_walls() {
+    // The cheap path runs along the top and down the right edge, but a normal crucible has to cut
+    // through the wall once to turn in time.
+    let content = r#"11111
+99991
+99991
+99991
+99991"#;
+    assert_eq!(least_loss(content, CRUCIBLE), Some(16));
+    assert_eq!(least_loss(content, ULTRA_CRUCIBLE), Some(8));
+
+    // The same grid transposed.
+    let content = r#"19999
+19999
+19999
+19999
+11111"#;
+    assert_eq!(least_loss(content, CRUCIBLE), Some(16));
+    assert_eq!(least_loss(content, ULTRA_CRUCIBLE), Some(8));
+
+    // A gap in a wall of 9s is cheaper to go around than to cross.
+    let content = r#"1111
+9991
+1111
+1999
+1111"#;
+    assert_eq!(least_loss(content, CRUCIBLE), Some(13));
+}
+
+#[test]
+fn test_demo() {
+    let content = read_to_string("input_demo.txt").unwrap();
+    assert_eq!(least_loss(&content, CRUCIBLE), Some(102));
+    assert_eq!(least_loss(&content, ULTRA_CRUCIBLE), Some(94));
+    let content = read_to_string("input_demo2.txt").unwrap();
+    assert_eq!(least_loss(&content, ULTRA_CRUCIBLE), Some(71));
+}
+
+#[test]
+fn test_k_best() {
+    let space = Space::new("1234");
+    let routes = space.k_best(CRUCIBLE, (0, 0), (0, 3), 3);
+    assert_eq!(routes.len(), 1);
+    assert_eq!(routes[0].render(&space), "1>>>\n");
+
+    // Straight there, or the long way round through the expensive block.
+    let space = Space::new("11\n19");
+    let routes = space.k_best(CRUCIBLE, (0, 0), (0, 1), 3);
+    let losses: Vec<u64> = routes.iter().map(|r| r.loss()).collect();
+    assert_eq!(losses, vec![1, 11]);
+    let routes = space.k_best(CRUCIBLE, (0, 0), (1, 1), 3);
+    let renders: Vec<String> = routes.iter().map(|r| r.render(&space)).collect();
+    assert_eq!(renders[..2], ["1>\n1v\n", "11\nv>\n"]);
+    assert_eq!(routes[0].loss(), 10);
+    assert_eq!(routes[1].loss(), 10);
+
+    // Staying put is one path however the crucible faces; the others go round the square.
+    let routes = space.k_best(CRUCIBLE, (0, 0), (0, 0), 3);
+    let losses: Vec<u64> = routes.iter().map(|r| r.loss()).collect();
+    assert_eq!(losses, vec![0, 12, 12]);
 }
@@ -1,9 +1,10 @@
 use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
 use std::env;
-use std::fs::read_to_string;
-use std::mem::size_of;
+use std::fmt;
+use std::fs::{read_to_string, write};
 use std::ops::Range;
+use std::process::exit;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum Direction {
@@ -11,83 +12,487 @@ enum Direction {
     R,
     U,
     D,
+    // The diagonals of the octagonal variant.
+    UL,
+    UR,
+    DL,
+    DR,
+}
+
+impl Direction {
+    fn new(s: &str) -> Self {
+        match s {
+            "R" => Direction::R,
+            "L" => Direction::L,
+            "U" => Direction::U,
+            "D" => Direction::D,
+            "UL" => Direction::UL,
+            "UR" => Direction::UR,
+            "DL" => Direction::DL,
+            "DR" => Direction::DR,
+            _ => panic!("unknown direction {}", s),
+        }
+    }
+
+    // The move of a single step as (x, y), `x` growing downwards.
+    fn delta(self) -> (i64, i64) {
+        match self {
+            Direction::L => (0, -1),
+            Direction::R => (0, 1),
+            Direction::U => (-1, 0),
+            Direction::D => (1, 0),
+            Direction::UL => (-1, -1),
+            Direction::UR => (-1, 1),
+            Direction::DL => (1, -1),
+            Direction::DR => (1, 1),
+        }
+    }
+
+    fn is_diagonal(self) -> bool {
+        let (dx, dy) = self.delta();
+        dx != 0 && dy != 0
+    }
+
+    // The direction after turning 90 degrees clockwise on screen.
+    fn right(self) -> Self {
+        match self {
+            Direction::R => Direction::D,
+            Direction::D => Direction::L,
+            Direction::L => Direction::U,
+            Direction::U => Direction::R,
+            Direction::UR => Direction::DR,
+            Direction::DR => Direction::DL,
+            Direction::DL => Direction::UL,
+            Direction::UL => Direction::UR,
+        }
+    }
+
+    fn left(self) -> Self {
+        self.right().right().right()
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 struct Dig {
     direction: Direction,
     step: usize,
+    // The colour the trench segment is painted with, as 0xrrggbb.
+    color: u32,
+}
+
+fn parse_color(field: &str) -> u32 {
+    let hex = field.strip_prefix("(#").unwrap().strip_suffix(')').unwrap();
+    u32::from_str_radix(hex, 16).unwrap()
+}
+
+// Turns the lines of a dig plan into digs. Lines are fed in order, so a decoder may carry state
+// from one line to the next.
+trait PlanDecoder {
+    fn decode(&mut self, line: &str) -> Vec<Dig>;
+}
+
+// `R 6 (#70c710)`: direction, step and colour columns.
+struct Columns;
+
+impl PlanDecoder for Columns {
+    fn decode(&mut self, line: &str) -> Vec<Dig> {
+        let mut line = line.split_whitespace();
+        let direction = match line.next().unwrap() {
+            d @ ("R" | "L" | "U" | "D") => Direction::new(d),
+            d => panic!("unknown direction {}", d),
+        };
+        let step = line.next().unwrap().parse().unwrap();
+        let color = parse_color(line.next().unwrap());
+        vec![Dig {
+            direction,
+            step,
+            color,
+        }]
+    }
+}
+
+// The real instruction hidden in the colour: five hex digits of step followed by the direction as
+// 0 (R), 1 (D), 2 (L) or 3 (U).
+struct Hex;
+
+impl PlanDecoder for Hex {
+    fn decode(&mut self, line: &str) -> Vec<Dig> {
+        let field = line.split_whitespace().nth(2).unwrap();
+        let color = parse_color(field);
+        let direction = match color & 0xf {
+            0 => Direction::R,
+            1 => Direction::D,
+            2 => Direction::L,
+            3 => Direction::U,
+            d => panic!("unknown direction {}", d),
+        };
+        vec![Dig {
+            direction,
+            step: (color >> 4) as usize,
+            color,
+        }]
+    }
+}
+
+// `R 5 (#0dc571)`: turn left (L) or right (R) or keep going forward (F) before digging. The digger
+// starts out facing right. The colour column is optional.
+struct Relative {
+    heading: Direction,
+}
+
+impl Default for Relative {
+    fn default() -> Self {
+        Self {
+            heading: Direction::R,
+        }
+    }
+}
+
+impl PlanDecoder for Relative {
+    fn decode(&mut self, line: &str) -> Vec<Dig> {
+        let mut line = line.split_whitespace();
+        self.heading = match line.next().unwrap() {
+            "L" => self.heading.left(),
+            "R" => self.heading.right(),
+            "F" => self.heading,
+            t => panic!("unknown turn {}", t),
+        };
+        let step = line.next().unwrap().parse().unwrap();
+        let color = line.next().map_or(0, parse_color);
+        vec![Dig {
+            direction: self.heading,
+            step,
+            color,
+        }]
+    }
+}
+
+// `6R5D2L`: runs of steps written as a count and a direction, the count being 1 when left out.
+// There are no colours.
+struct RunLength;
+
+impl PlanDecoder for RunLength {
+    fn decode(&mut self, line: &str) -> Vec<Dig> {
+        let mut digs = Vec::new();
+        let mut count = String::new();
+        for c in line.chars().filter(|c| !c.is_whitespace()) {
+            if c.is_ascii_digit() {
+                count.push(c);
+                continue;
+            }
+            let step = if count.is_empty() {
+                1
+            } else {
+                count.parse().unwrap()
+            };
+            count.clear();
+            digs.push(Dig {
+                direction: Direction::new(&c.to_string()),
+                step,
+                color: 0,
+            });
+        }
+        if !count.is_empty() {
+            panic!("run of {} without a direction", count);
+        }
+        digs
+    }
+}
+
+// `UR 3 (#70c710)`: like `Columns`, but the digger may also move diagonally (UL, UR, DL, DR), one
+// block diagonally per step.
+struct Octagonal;
+
+impl PlanDecoder for Octagonal {
+    fn decode(&mut self, line: &str) -> Vec<Dig> {
+        let mut line = line.split_whitespace();
+        let direction = Direction::new(line.next().unwrap());
+        let step = line.next().unwrap().parse().unwrap();
+        let color = line.next().map_or(0, parse_color);
+        vec![Dig {
+            direction,
+            step,
+            color,
+        }]
+    }
+}
+
+fn decoder(name: &str) -> Option<Box<dyn PlanDecoder>> {
+    Some(match name {
+        "columns" => Box::new(Columns),
+        "hex" => Box::new(Hex),
+        "relative" => Box::new(Relative::default()),
+        "rle" => Box::new(RunLength),
+        "octagonal" => Box::new(Octagonal),
+        _ => return None,
+    })
 }
 
 struct Plan(Vec<Dig>);
 
 impl Plan {
-    fn new(content: &str) -> Self {
-        let mut inner = Vec::new();
-        content.lines().for_each(|line| {
-            let mut line = line.split_whitespace();
-            let direction = match line.next().unwrap() {
-                "R" => Direction::R,
-                "L" => Direction::L,
-                "U" => Direction::U,
-                "D" => Direction::D,
-                _ => unreachable!(),
-            };
-            let step = line.next().unwrap().parse().unwrap();
-            inner.push(Dig { direction, step });
+    fn new(content: &str, decoder: &mut dyn PlanDecoder) -> Self {
+        Self(
+            content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .flat_map(|line| decoder.decode(line))
+                .collect(),
+        )
+    }
+
+    fn is_orthogonal(&self) -> bool {
+        self.0.iter().all(|dig| !dig.direction.is_diagonal())
+    }
+
+    // The corners of the trench in dig order, starting and ending at the origin. `x` grows
+    // downwards and `y` to the right, as for `Point`.
+    fn vertices(&self) -> Vec<(i64, i64)> {
+        let mut p = (0, 0);
+        let mut l = vec![p];
+        self.0.iter().for_each(|dig| {
+            let step = dig.step as i64;
+            let (dx, dy) = dig.direction.delta();
+            p = (p.0 + dx * step, p.1 + dy * step);
+            l.push(p);
         });
+        l
+    }
 
-        // Merge the lines on the same direction to guarantee one direction has only one line
-        if inner[0].direction == inner.last().unwrap().direction {
-            let first_step = inner.first().unwrap().step;
-            let last = inner.last_mut().unwrap();
-            last.step += first_step;
-            inner = inner[1..].to_vec();
+    // The lagoon volume, i.e. the trench plus the blocks it encloses. The shoelace formula gives
+    // the area A of the polygon through the centres of the trench blocks, and by Pick's theorem
+    // that polygon has A - B/2 + 1 blocks strictly inside it, B being the number of trench
+    // blocks. Adding the trench itself gives A + B/2 + 1. A diagonal step digs a single block too,
+    // so this holds for octagonal plans as well.
+    fn area(&self) -> i64 {
+        let vertices = self.vertices();
+        let twice_area: i64 = vertices
+            .windows(2)
+            .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+            .sum();
+        let boundary: i64 = self.0.iter().map(|dig| dig.step as i64).sum();
+        (twice_area.abs() + boundary) / 2 + 1
+    }
+
+    // The trench as an SVG drawing, every segment in its own colour over the filled lagoon.
+    fn svg(&self) -> String {
+        let vertices = self.vertices();
+        let min_x = vertices.iter().map(|v| v.0).min().unwrap();
+        let max_x = vertices.iter().map(|v| v.0).max().unwrap();
+        let min_y = vertices.iter().map(|v| v.1).min().unwrap();
+        let max_y = vertices.iter().map(|v| v.1).max().unwrap();
+        // Lines are one block wide, but stay visible when the plan is huge.
+        let stroke = max(1, max(max_x - min_x, max_y - min_y) / 500);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min_y - stroke,
+            min_x - stroke,
+            max_y - min_y + 2 * stroke,
+            max_x - min_x + 2 * stroke
+        );
+        let points: Vec<String> = vertices
+            .iter()
+            .map(|(x, y)| format!("{},{}", y, x))
+            .collect();
+        out.push_str(&format!(
+            "<polygon points=\"{}\" fill=\"lightgrey\"/>\n",
+            points.join(" ")
+        ));
+        for (dig, w) in self.0.iter().zip(vertices.windows(2)) {
+            out.push_str(&format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#{:06x}\" \
+                 stroke-width=\"{}\" stroke-linecap=\"square\"/>\n",
+                w[0].1, w[0].0, w[1].1, w[1].0, dig.color, stroke
+            ));
         }
+        out.push_str("</svg>\n");
+        out
+    }
 
-        Self(inner)
-    }
-    fn new2(content: &str) -> Self {
-        let mut inner = Vec::new();
-        content.lines().for_each(|line| {
-            let mut line = line.split_whitespace();
-            line.next();
-            line.next();
-            let last = line
-                .next()
-                .unwrap()
-                .strip_prefix("(#")
-                .unwrap()
-                .strip_suffix(")")
-                .unwrap();
-            let step_bytes =
-                hex::decode([String::from("0"), last.chars().take(5).collect()].concat()).unwrap();
-            let step_bytes = [
-                [0].repeat(size_of::<usize>() - step_bytes.len()),
-                step_bytes,
-            ]
-            .concat();
-            let step = usize::from_be_bytes(step_bytes.try_into().unwrap());
-            let direction_str: String = last.chars().skip(5).take(1).collect();
-            let direction = match direction_str.as_str() {
-                "0" => Direction::R,
-                "1" => Direction::D,
-                "2" => Direction::L,
-                "3" => Direction::U,
-                _ => unreachable!(),
-            };
-            inner.push(Dig { direction, step });
-        });
+    // Checks that the trench is a simple closed loop: it has to end where it started and no two
+    // segments may meet except consecutive ones at their shared corner. Segments are numbered
+    // from 1 in input order.
+    fn validate(&self) -> Result<Outline, Vec<PlanError>> {
+        if self.0.is_empty() {
+            return Err(vec![PlanError::Empty]);
+        }
+        let vertices = self.vertices();
+        let mut errors = Vec::new();
+        let end = *vertices.last().unwrap();
+        let closed = end == (0, 0);
+        if !closed {
+            errors.push(PlanError::NotClosed { end });
+        }
+        for (i, dig) in self.0.iter().enumerate() {
+            if dig.step == 0 {
+                errors.push(PlanError::ZeroLength { segment: i + 1 });
+            }
+        }
 
-        // Merge the lines on the same direction to guarantee one direction has only one line
-        if inner[0].direction == inner.last().unwrap().direction {
-            let first_step = inner.first().unwrap().step;
-            let last = inner.last_mut().unwrap();
-            last.step += first_step;
-            inner = inner[1..].to_vec();
+        let n = self.0.len();
+        let segments: Vec<Segment> = self
+            .0
+            .iter()
+            .zip(&vertices)
+            .map(|(dig, start)| (*start, dig.direction.delta(), dig.step as i64))
+            .collect();
+        for i in 0..n {
+            for j in i + 1..n {
+                if self.0[i].step == 0 || self.0[j].step == 0 {
+                    continue;
+                }
+                let Some((from, to)) = meet(segments[i], segments[j]) else {
+                    continue;
+                };
+                let corner = if j == i + 1 {
+                    Some(HalfPoint::new(vertices[j]))
+                } else if closed && i == 0 && j == n - 1 {
+                    Some(HalfPoint::new(vertices[0]))
+                } else {
+                    None
+                };
+                let (a, b) = (i + 1, j + 1);
+                if from != to {
+                    errors.push(PlanError::Overlap { a, b, from, to });
+                } else if corner != Some(from) {
+                    errors.push(PlanError::Crossing { a, b, at: from });
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
         }
 
-        Self(inner)
+        let twice_area: i64 = vertices
+            .windows(2)
+            .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+            .sum();
+        // `x` points down and `y` right, so a negative area means clockwise on screen.
+        let winding = if twice_area < 0 {
+            Winding::Clockwise
+        } else {
+            Winding::CounterClockwise
+        };
+        let min = (
+            vertices.iter().map(|v| v.0).min().unwrap(),
+            vertices.iter().map(|v| v.1).min().unwrap(),
+        );
+        let max = (
+            vertices.iter().map(|v| v.0).max().unwrap(),
+            vertices.iter().map(|v| v.1).max().unwrap(),
+        );
+        Ok(Outline { winding, min, max })
+    }
+}
+
+// A segment of the trench as its start, the move of a single step and the number of steps.
+type Segment = ((i64, i64), (i64, i64), i64);
+
+// A position in half blocks. Two diagonal segments may cross between blocks, but always at a whole
+// number of half blocks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct HalfPoint(i64, i64);
+
+impl HalfPoint {
+    fn new((x, y): (i64, i64)) -> Self {
+        HalfPoint(2 * x, 2 * y)
+    }
+}
+
+impl fmt::Display for HalfPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |v: i64| {
+            if v % 2 == 0 {
+                (v / 2).to_string()
+            } else {
+                (v as f64 / 2.0).to_string()
+            }
+        };
+        write!(f, "({}, {})", show(self.0), show(self.1))
+    }
+}
+
+// Where two segments meet, as the first and last shared point along `a`, or None if they don't.
+// Working in half blocks keeps every intersection exact.
+fn meet(a: Segment, b: Segment) -> Option<(HalfPoint, HalfPoint)> {
+    let cross = |u: (i64, i64), v: (i64, i64)| u.0 * v.1 - u.1 * v.0;
+    let dot = |u: (i64, i64), v: (i64, i64)| u.0 * v.0 + u.1 * v.1;
+    let (p, da, la) = (HalfPoint::new(a.0), a.1, 2 * a.2);
+    let (q, db, lb) = (HalfPoint::new(b.0), b.1, 2 * b.2);
+    let w = (q.0 - p.0, q.1 - p.1);
+    let at = |t: i64| HalfPoint(p.0 + da.0 * t, p.1 + da.1 * t);
+
+    let denom = cross(da, db);
+    if denom == 0 {
+        // Parallel segments only share points when they lie on the same line, and then they
+        // share the overlap of their extents along it.
+        if cross(da, w) != 0 {
+            return None;
+        }
+        let norm = dot(da, da);
+        let s0 = dot(w, da) / norm;
+        let s1 = s0 + lb * dot(db, da) / norm;
+        let (lo, hi) = (max(0, min(s0, s1)), min(la, max(s0, s1)));
+        return (lo <= hi).then(|| (at(lo), at(hi)));
+    }
+    let t = cross(w, db) / denom;
+    let u = cross(w, da) / denom;
+    ((0..=la).contains(&t) && (0..=lb).contains(&u)).then(|| (at(t), at(t)))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+// The shape of a valid plan: which way it is dug and the corners of its bounding box.
+#[derive(Debug)]
+struct Outline {
+    winding: Winding,
+    min: (i64, i64),
+    max: (i64, i64),
+}
+
+#[derive(Debug, PartialEq)]
+enum PlanError {
+    Empty,
+    NotClosed {
+        end: (i64, i64),
+    },
+    ZeroLength {
+        segment: usize,
+    },
+    Crossing {
+        a: usize,
+        b: usize,
+        at: HalfPoint,
+    },
+    Overlap {
+        a: usize,
+        b: usize,
+        from: HalfPoint,
+        to: HalfPoint,
+    },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::Empty => write!(f, "the plan has no instructions"),
+            PlanError::NotClosed { end } => {
+                write!(f, "the trench ends at {:?} instead of the origin", end)
+            }
+            PlanError::ZeroLength { segment } => write!(f, "segment {} has length 0", segment),
+            PlanError::Crossing { a, b, at } => {
+                write!(f, "segments {} and {} meet at {}", a, b, at)
+            }
+            PlanError::Overlap { a, b, from, to } => write!(
+                f,
+                "segments {} and {} overlap from {} to {}",
+                a, b, from, to
+            ),
+        }
     }
 }
 
@@ -102,7 +507,7 @@ impl Point {
         let x = self.x as isize + x;
         let y = self.y as isize + y;
         if x < 0 || y < 0 {
-            return None;
+            None
         } else {
             Some(Point {
                 x: x as usize,
@@ -135,21 +540,21 @@ impl Lines {
         Self(HashMap::new())
     }
     fn insert(&mut self, axis: usize, line: Line) {
-        self.0.entry(axis).or_insert(HashSet::new()).insert(line);
+        self.0.entry(axis).or_default().insert(line);
     }
     fn all_lines_cross(&self, range: Range<usize>) -> Vec<&Line> {
         let mut l = Vec::new();
         self.0.iter().for_each(|(_, set)| {
             set.iter().for_each(|line| {
-                let line_range;
-                match line.direction {
+                let line_range = match line.direction {
                     Direction::L | Direction::R => {
-                        line_range = min(line.start.y, line.end.y)..=max(line.start.y, line.end.y);
+                        min(line.start.y, line.end.y)..=max(line.start.y, line.end.y)
                     }
                     Direction::D | Direction::U => {
-                        line_range = min(line.start.x, line.end.x)..=max(line.start.x, line.end.x);
+                        min(line.start.x, line.end.x)..=max(line.start.x, line.end.x)
                     }
-                }
+                    _ => unreachable!(),
+                };
                 if line_range.contains(&range.start) && line_range.contains(&range.end) {
                     l.push(line);
                 }
@@ -161,12 +566,25 @@ impl Lines {
 
 impl Edges {
     fn new(plan: &Plan) -> Self {
+        assert!(
+            plan.is_orthogonal(),
+            "the scanline only handles horizontal and vertical trenches"
+        );
+        // Merge the lines on the same direction to guarantee one direction has only one line
+        let mut digs = plan.0.clone();
+        if digs[0].direction == digs.last().unwrap().direction {
+            let first_step = digs.first().unwrap().step;
+            let last = digs.last_mut().unwrap();
+            last.step += first_step;
+            digs.remove(0);
+        }
+
         let mut h_lines = Lines::new();
         let mut v_lines = Lines::new();
 
         let mut p = (0, 0);
         let (mut min_x, mut max_x, mut min_y, mut max_y) = (0, 0, 0, 0);
-        plan.0.iter().for_each(|dig| {
+        digs.iter().for_each(|dig| {
             let step = dig.step as isize;
             let direction = dig.direction;
             match direction {
@@ -186,11 +604,12 @@ impl Edges {
                     max_x = max(max_x, p.0 + step);
                     p = (p.0 + step, p.1);
                 }
+                _ => unreachable!(),
             }
         });
 
         let mut p = Point { x: 0, y: 0 }.offset(-min_x, -min_y).unwrap();
-        plan.0.iter().for_each(|dig| {
+        digs.iter().for_each(|dig| {
             let step = dig.step as isize;
             let start = p;
             let end;
@@ -228,6 +647,7 @@ impl Edges {
                         },
                     );
                 }
+                _ => unreachable!(),
             }
             p = end;
         });
@@ -245,7 +665,7 @@ impl Edges {
             .zip(hline_axis[1..].iter())
             .for_each(|(&&top_x, &&bottom_x)| {
                 let mut vlines: Vec<_> = self.v_lines.all_lines_cross(top_x..bottom_x);
-                vlines.sort_by(|l1, l2| l1.start.y.cmp(&l2.start.y));
+                vlines.sort_by_key(|l| l.start.y);
 
                 let mut width = 0;
                 vlines[..vlines.len() - 1]
@@ -320,16 +740,87 @@ impl Edges {
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        panic!(
+            "./exe <file> [--decoder columns|hex|relative|rle|octagonal]... [--check] [--outline] \
+             [--svg <file>]"
+        );
     }
     let content = read_to_string(&args[1]).unwrap();
+    let flags = &args[2..];
+    let check = flags.iter().any(|a| a == "--check");
+    let outline = flags.iter().any(|a| a == "--outline");
+    // The argument following `flag` wherever it appears.
+    let values = |flag: &str| -> Vec<&str> {
+        let mut values = Vec::new();
+        for (i, a) in flags.iter().enumerate() {
+            if a == flag {
+                match flags.get(i + 1) {
+                    Some(value) => values.push(value.as_str()),
+                    None => {
+                        println!("{} expects an argument", flag);
+                        exit(1);
+                    }
+                }
+            }
+        }
+        values
+    };
+    let mut names = values("--decoder");
+    if names.is_empty() {
+        names = vec!["columns", "hex"];
+    }
+    let svg = values("--svg").pop();
 
-    let plan = Plan::new(&content);
-    let edges = Edges::new(&plan);
-    println!("{}", edges.area());
+    let plans: Vec<Plan> = names
+        .iter()
+        .map(|name| match decoder(name) {
+            Some(mut decoder) => Plan::new(&content, decoder.as_mut()),
+            None => {
+                println!(
+                    "unknown decoder {}, expected one of columns, hex, relative, rle or octagonal",
+                    name
+                );
+                exit(1);
+            }
+        })
+        .collect();
+    let mut rejected = false;
+    for (name, plan) in names.iter().zip(&plans) {
+        match plan.validate() {
+            Ok(o) => {
+                if outline {
+                    println!(
+                        "{}: {:?}, bounding box {:?} to {:?}",
+                        name, o.winding, o.min, o.max
+                    );
+                }
+            }
+            Err(errors) => {
+                println!("{}: the dig plan is not a simple closed loop", name);
+                for e in errors {
+                    println!("  {}", e);
+                }
+                rejected = true;
+                continue;
+            }
+        }
+        let area = plan.area();
+        println!("{}", area);
+        if check && plan.is_orthogonal() {
+            let scanline = Edges::new(plan).area();
+            println!("shoelace+pick: {}, scanline: {}", area, scanline);
+            if scanline as i64 != area {
+                panic!("lagoon areas disagree");
+            }
+        }
+    }
 
-    let plan = Plan::new2(&content);
-    let edges = Edges::new(&plan);
-    println!("{}", edges.area());
+    // The colours belong to the first reading of the plan, so that is the one drawn.
+    if let Some(file) = svg {
+        write(file, plans[0].svg()).unwrap();
+    }
+    if rejected {
+        exit(1);
+    }
 }
@@ -0,0 +1,323 @@
+use crate::{EvaResult, PartRange, Rule, Workflows};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+// What range propagation from `in` reveals about a set of workflows. Rules are numbered from 1.
+pub struct Analysis {
+    pub has_start: bool,
+    // Rules sending parts to a workflow that does not exist, as (workflow, rule, target).
+    pub undefined: Vec<(String, usize, String)>,
+    // Workflows no part ever enters.
+    pub unreachable: Vec<String>,
+    // Rules of reachable workflows that no part ever gets to apply, as (workflow, rule).
+    pub dead_rules: Vec<(String, usize)>,
+    // Parts that never finish, as the workflows they keep going through and how many parts.
+    pub loops: Vec<(Vec<String>, u128)>,
+    entered: HashSet<String>,
+    fired: HashSet<(String, usize)>,
+}
+
+pub fn analyze(workflows: &Workflows, universe: PartRange) -> Analysis {
+    let propagation = workflows.propagate(universe);
+
+    let mut undefined = Vec::new();
+    let mut dead_rules = Vec::new();
+    for (name, workflow) in &workflows.0 {
+        for (i, rule) in workflow.rules.iter().enumerate() {
+            if let EvaResult::Move(next) | EvaResult::Call(next) = &rule.next {
+                if !workflows.0.contains_key(next) {
+                    undefined.push((name.clone(), i + 1, next.clone()));
+                }
+            }
+            if propagation.entered.contains(name) && !propagation.fired.contains(&(name.clone(), i))
+            {
+                dead_rules.push((name.clone(), i + 1));
+            }
+        }
+    }
+    let mut unreachable: Vec<String> = workflows
+        .0
+        .keys()
+        .filter(|name| !propagation.entered.contains(*name))
+        .cloned()
+        .collect();
+    // The same loop is usually found by many branches.
+    let mut loops: BTreeMap<Vec<String>, u128> = BTreeMap::new();
+    for (path, count) in propagation.loops {
+        *loops.entry(path).or_default() += count;
+    }
+    undefined.sort();
+    dead_rules.sort();
+    unreachable.sort();
+
+    Analysis {
+        has_start: workflows.0.contains_key("in"),
+        undefined,
+        unreachable,
+        dead_rules,
+        loops: loops.into_iter().collect(),
+        entered: propagation.entered,
+        fired: propagation.fired,
+    }
+}
+
+impl Analysis {
+    pub fn describe(&self) -> String {
+        let mut out = String::new();
+        if !self.has_start {
+            out.push_str("there is no workflow named in\n");
+        }
+        for (name, rule, target) in &self.undefined {
+            out.push_str(&format!(
+                "rule {} of {} goes to undefined workflow {}\n",
+                rule, name, target
+            ));
+        }
+        for name in &self.unreachable {
+            out.push_str(&format!("workflow {} is never reached\n", name));
+        }
+        for (name, rule) in &self.dead_rules {
+            out.push_str(&format!("rule {} of {} never applies\n", rule, name));
+        }
+        for (path, count) in &self.loops {
+            out.push_str(&format!(
+                "{} parts loop forever through {}\n",
+                count,
+                path.join(" -> ")
+            ));
+        }
+        if out.is_empty() {
+            out.push_str("no problems found\n");
+        }
+        out
+    }
+}
+
+// An equivalent rule set for parts rated within the analysed ranges, with unreachable workflows
+// and dead rules dropped, trailing rules that go where the final rule goes merged into it, and
+// workflows that only forward to another target inlined. `in` comes first, then the workflows in
+// the order they are first referenced.
+pub fn simplify(workflows: &Workflows, analysis: &Analysis) -> String {
+    let mut rules: HashMap<String, Vec<Rule>> = HashMap::new();
+    for name in &analysis.entered {
+        let mut kept: Vec<Rule> = workflows.0[name]
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| analysis.fired.contains(&(name.clone(), *i)))
+            .map(|(_, rule)| rule.clone())
+            .collect();
+        // Every part that gets past the other rules meets the last one that applies to any.
+        if let Some(last) = kept.last_mut() {
+            last.condition = None;
+        }
+        rules.insert(name.clone(), kept);
+    }
+
+    loop {
+        let mut changed = false;
+        for kept in rules.values_mut() {
+            while kept.len() >= 2
+                && kept[kept.len() - 2].next == kept[kept.len() - 1].next
+                && !matches!(kept[kept.len() - 1].next, EvaResult::Call(_))
+            {
+                kept.remove(kept.len() - 2);
+                changed = true;
+            }
+        }
+        // Moving to a workflow that only calls another is not a call from the workflow moved
+        // from, so such a workflow stays while anything moves to it.
+        let moved_to = |name: &String| {
+            rules
+                .values()
+                .flatten()
+                .any(|rule| rule.next == EvaResult::Move(name.clone()))
+        };
+        let forward = rules.iter().find_map(|(name, kept)| match &kept[..] {
+            [only]
+                if name != "in"
+                    && only.next != EvaResult::Move(name.clone())
+                    && only.next != EvaResult::Call(name.clone())
+                    && !(matches!(only.next, EvaResult::Call(_)) && moved_to(name)) =>
+            {
+                Some((name.clone(), only.next.clone()))
+            }
+            _ => None,
+        });
+        if let Some((name, target)) = forward {
+            rules.remove(&name);
+            for kept in rules.values_mut() {
+                // Calling a workflow that returns straight away just carries on with the next
+                // rule, so the call is dropped unless it is the final rule.
+                if target == EvaResult::Return {
+                    let last = kept.len() - 1;
+                    let mut i = 0;
+                    kept.retain(|rule| {
+                        i += 1;
+                        i > last || rule.next != EvaResult::Call(name.clone())
+                    });
+                }
+                for rule in kept.iter_mut() {
+                    rule.next = match (&rule.next, &target) {
+                        (EvaResult::Move(n), _) if *n == name => target.clone(),
+                        // A call that can only end by accepting or rejecting never returns, and
+                        // one that returns as the final rule returns in turn.
+                        (
+                            EvaResult::Call(n),
+                            EvaResult::Accept | EvaResult::Reject | EvaResult::Return,
+                        ) if *n == name => target.clone(),
+                        (EvaResult::Call(n), EvaResult::Move(m) | EvaResult::Call(m))
+                            if *n == name =>
+                        {
+                            EvaResult::Call(m.clone())
+                        }
+                        _ => continue,
+                    };
+                }
+            }
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut out = String::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<String> = VecDeque::from(["in".to_string()]);
+    while let Some(name) = queue.pop_front() {
+        let Some(kept) = rules.get(&name) else {
+            continue;
+        };
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let kept: Vec<String> = kept.iter().map(|rule| rule.to_string()).collect();
+        out.push_str(&format!("{}{{{}}}\n", name, kept.join(",")));
+        for rule in &rules[&name] {
+            if let EvaResult::Move(next) | EvaResult::Call(next) = &rule.next {
+                queue.push_back(next.clone());
+            }
+        }
+    }
+    out
+}
+
+// The workflow graph in Graphviz DOT. Calls are dashed, unreachable workflows and rules that never
+// apply are grey, and undefined workflows are red.
+pub fn dot(workflows: &Workflows, analysis: &Analysis) -> String {
+    let mut out = String::from("digraph workflows {\n");
+    out.push_str("  \"A\" [shape=box, color=green];\n");
+    out.push_str("  \"R\" [shape=box, color=red];\n");
+    out.push_str("  \"^\" [shape=box, label=\"return\"];\n");
+    let mut names: Vec<&String> = workflows.0.keys().collect();
+    names.sort();
+    for name in &names {
+        if analysis.unreachable.contains(name) {
+            out.push_str(&format!("  \"{}\" [color=grey, fontcolor=grey];\n", name));
+        }
+    }
+    for (_, _, target) in &analysis.undefined {
+        out.push_str(&format!("  \"{}\" [color=red, style=dashed];\n", target));
+    }
+    for name in names {
+        for (i, rule) in workflows.0[name].rules.iter().enumerate() {
+            let target = match &rule.next {
+                EvaResult::Accept => "A",
+                EvaResult::Reject => "R",
+                EvaResult::Move(next) | EvaResult::Call(next) => next,
+                EvaResult::Return => "^",
+            };
+            let mut attrs = Vec::new();
+            if let Some(c) = &rule.condition {
+                attrs.push(format!("label=\"{}\"", c));
+            }
+            if let EvaResult::Call(_) = rule.next {
+                attrs.push("style=dashed".to_string());
+            }
+            if analysis.dead_rules.contains(&(name.clone(), i + 1)) {
+                attrs.push("color=grey".to_string());
+                attrs.push("fontcolor=grey".to_string());
+            }
+            out.push_str(&format!(
+                "  \"{}\" -> \"{}\" [{}];\n",
+                name,
+                target,
+                attrs.join(", ")
+            ));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Checks that the simplified workflows treat every part rated with `values` in each tested
+// category as the original ones do. Loops are only compared as loops, since the simplified
+// workflows may be caught going round a different way.
+#[cfg(test)]
+fn assert_simplify_keeps_outcomes(text: &str, values: &[u64]) {
+    use crate::{parse, Part};
+
+    let (workflows, _) = parse(text).unwrap();
+    let categories = workflows.categories();
+    let analysis = analyze(
+        &workflows,
+        PartRange::new(categories.iter().copied(), 1..4001),
+    );
+    let simplified = simplify(&workflows, &analysis);
+    let (simple, _) = parse(&simplified).unwrap();
+
+    let mut parts: Vec<BTreeMap<String, u64>> = vec![BTreeMap::new()];
+    for category in &categories {
+        parts = parts
+            .into_iter()
+            .flat_map(|part| {
+                values.iter().map(move |v| {
+                    let mut part = part.clone();
+                    part.insert(category.to_string(), *v);
+                    part
+                })
+            })
+            .collect();
+    }
+    let outcome = |workflows: &Workflows, part: &Part| {
+        workflows
+            .eva(part)
+            .map_err(|e| e.split(" through ").next().unwrap().to_string())
+    };
+    for part in parts.into_iter().map(Part) {
+        assert_eq!(
+            outcome(&workflows, &part),
+            outcome(&simple, &part),
+            "part {} with\n{}simplified to\n{}",
+            part,
+            text,
+            simplified
+        );
+    }
+}
+
+#[test]
+fn test_simplify_calls() {
+    // A call to a workflow that returns straight away does nothing.
+    assert_simplify_keeps_outcomes("in{a<10:@fwd,A}\nfwd{^}\n", &[1, 9, 10, 4000]);
+    assert_simplify_keeps_outcomes("in{a<10:A,@fwd}\nfwd{^}\n", &[1, 9, 10, 4000]);
+    // Calls through a workflow that only calls or moves on go straight to the end of the chain.
+    assert_simplify_keeps_outcomes(
+        "in{a<10:@f,R}\nf{@g}\ng{h}\nh{a<5:A,^}\n",
+        &[1, 4, 5, 9, 10],
+    );
+    // Moving to a workflow that only calls returns from it afterwards, which here rejects.
+    assert_simplify_keeps_outcomes("in{a<10:fwd,A}\nfwd{@m}\nm{^}\n", &[1, 9, 10, 4000]);
+    assert_simplify_keeps_outcomes("in{a<10:fwd,A}\nfwd{@m}\nm{a<5:R,^}\n", &[1, 5, 9, 10]);
+}
+
+#[test]
+fn test_simplify_loops() {
+    assert_simplify_keeps_outcomes(
+        "in{x<10:a,x<20:nope,y>5:@rec,R}\nrec{@rec}\n",
+        &[1, 5, 6, 9, 10, 19, 20, 4000],
+    );
+    assert_simplify_keeps_outcomes("in{x<10:a,A}\na{b}\nb{a}\n", &[1, 9, 10]);
+    assert_simplify_keeps_outcomes("in{x<10:@a,A}\na{@b}\nb{@a}\n", &[1, 9, 10]);
+    assert_simplify_keeps_outcomes("in{x<10:@a,A}\na{@b}\nb{a}\n", &[1, 9, 10]);
+}
@@ -1,371 +1,771 @@
-use std::collections::HashMap;
+mod analysis;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
-use std::fs::read_to_string;
+use std::fmt;
+use std::fs::{read_to_string, write};
 use std::ops::Range;
+use std::process::exit;
 
-#[derive(Debug)]
+// The workflow language. Whitespace is ignored.
+//
+//     workflow  := name "{" rule ("," rule)* "}"
+//     rule      := (condition ":")? target
+//     condition := all ("||" all)*
+//     all       := test ("&&" test)*
+//     test      := category op number | "(" condition ")"
+//     op        := "<" | ">" | "<=" | ">=" | "==" | "!="
+//     target    := "A"              accept the part
+//                | "R"              reject the part
+//                | name             continue with that workflow
+//                | "@" name         run that workflow, and carry on with the next rule if it returns
+//                | "^"              return to the workflow that called this one
+//     name      := letter+
+//     number    := digit+
+//
+// The first rule whose condition holds applies, and the last rule must not have a condition.
+// Categories are not fixed: any name may be tested, and parts may rate any set of categories.
+// Returning when nothing called the current workflow rejects the part. Testing a category the part
+// has no rating in is an error.
+#[derive(Debug, Clone, PartialEq)]
 enum EvaResult {
     Reject,
     Accept,
     Move(String),
+    Call(String),
+    Return,
 }
 
-impl From<&str> for EvaResult {
-    fn from(value: &str) -> Self {
-        match value {
-            "A" => EvaResult::Accept,
-            "R" => EvaResult::Reject,
-            _ => EvaResult::Move(value.to_string()),
+impl fmt::Display for EvaResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvaResult::Reject => write!(f, "R"),
+            EvaResult::Accept => write!(f, "A"),
+            EvaResult::Move(name) => write!(f, "{}", name),
+            EvaResult::Call(name) => write!(f, "@{}", name),
+            EvaResult::Return => write!(f, "^"),
         }
     }
 }
 
-#[derive(Debug)]
-struct Workflows<'a>(HashMap<&'a str, Workflow>);
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Op {
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
 
-impl<'a> Workflows<'a> {
-    fn eva(&self, part: &Part) -> Option<u64> {
-        let mut workflow = &self.0["in"];
-        loop {
-            let res = workflow.eva(part);
-            match res {
-                EvaResult::Reject => return None,
-                EvaResult::Accept => return Some(part.sum()),
-                EvaResult::Move(next) => {
-                    workflow = &self.0[next.as_str()];
-                }
-            }
+impl Op {
+    // The op that holds exactly when this one does not.
+    fn negate(self) -> Self {
+        match self {
+            Op::Lt => Op::Ge,
+            Op::Gt => Op::Le,
+            Op::Le => Op::Gt,
+            Op::Ge => Op::Lt,
+            Op::Eq => Op::Ne,
+            Op::Ne => Op::Eq,
         }
     }
 
-    fn acceptable_ranges(&self) -> Vec<PartRange> {
-        let mut wl = vec![(
-            "in".to_string(),
-            PartRange {
-                x: 1..4001,
-                s: 1..4001,
-                a: 1..4001,
-                m: 1..4001,
-            },
-        )];
-        let mut valid_ranges = Vec::new();
-        while wl.len() != 0 {
-            let mut new_wl = Vec::new();
-            for (name, range) in wl {
-                let moves = self.0[name.as_str()].next_moves(&range);
-                for mov in moves {
-                    match mov.next {
-                        EvaResult::Reject => continue,
-                        EvaResult::Accept => valid_ranges.push(mov.range),
-                        EvaResult::Move(name) => new_wl.push((name, mov.range.clone())),
-                    }
-                }
-            }
-            wl = new_wl;
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Le => "<=",
+            Op::Ge => ">=",
+            Op::Eq => "==",
+            Op::Ne => "!=",
         }
-        valid_ranges
+    }
+
+    fn holds(self, left: u64, right: u64) -> bool {
+        match self {
+            Op::Lt => left < right,
+            Op::Gt => left > right,
+            Op::Le => left <= right,
+            Op::Ge => left >= right,
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+        }
+    }
+
+    // The values of `range` for which `value op val` holds, as disjoint ranges.
+    fn narrow(self, range: &Range<u64>, val: u64) -> Vec<Range<u64>> {
+        let bounds = match self {
+            Op::Lt => vec![(0, val)],
+            Op::Le => vec![(0, val + 1)],
+            Op::Gt => vec![(val + 1, u64::MAX)],
+            Op::Ge => vec![(val, u64::MAX)],
+            Op::Eq => vec![(val, val + 1)],
+            Op::Ne => vec![(0, val), (val + 1, u64::MAX)],
+        };
+        bounds
+            .into_iter()
+            .map(|(start, end)| start.max(range.start)..end.min(range.end))
+            .filter(|r| !r.is_empty())
+            .collect()
     }
 }
 
-#[derive(Debug)]
-struct Workflow {
-    rule: String,
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Test { category: String, op: Op, val: u64 },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
 }
 
-impl Workflow {
-    fn rules(&self) -> Vec<(Option<&str>, EvaResult)> {
-        let mut rules = Vec::new();
-        for rule in self.rule.split(",") {
-            if rule.contains(":") {
-                let [condition, res] = rule.split(":").collect::<Vec<&str>>().try_into().unwrap();
-                rules.push((Some(condition), EvaResult::from(res)));
-            } else {
-                rules.push((None, EvaResult::from(rule)));
+impl Condition {
+    // Whether the part meets the condition, or an error if it tests a category the part has no
+    // rating in before the outcome is settled.
+    fn meet(&self, part: &Part) -> Result<bool, String> {
+        match self {
+            Condition::Test { category, op, val } => match part.get(category) {
+                Some(v) => Ok(op.holds(v, *val)),
+                None => Err(format!("no {} rating", category)),
+            },
+            Condition::All(cs) => {
+                for c in cs {
+                    if !c.meet(part)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Condition::Any(cs) => {
+                for c in cs {
+                    if c.meet(part)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
             }
         }
-        rules
     }
 
-    fn eva(&self, part: &Part) -> EvaResult {
-        for (condition, res) in self.rules() {
-            if let Some(condition) = condition {
-                if part.meet(condition) {
-                    return res;
+    // Splits `range` into the parts that meet the condition and the parts that don't, each as a
+    // list of disjoint ranges.
+    fn narrow(&self, range: &PartRange) -> (Vec<PartRange>, Vec<PartRange>) {
+        match self {
+            Condition::Test { category, op, val } => {
+                let r = &range.0[category];
+                let split = |op: Op| {
+                    op.narrow(r, *val)
+                        .into_iter()
+                        .map(|r| range.with(category, r))
+                        .collect()
+                };
+                (split(*op), split(op.negate()))
+            }
+            Condition::All(cs) => {
+                let mut valid = vec![range.clone()];
+                let mut invalid = Vec::new();
+                for c in cs {
+                    let mut next = Vec::new();
+                    for r in &valid {
+                        let (v, i) = c.narrow(r);
+                        next.extend(v);
+                        invalid.extend(i);
+                    }
+                    valid = next;
                 }
-            } else {
-                return res;
+                (valid, invalid)
+            }
+            Condition::Any(cs) => {
+                let mut valid = Vec::new();
+                let mut invalid = vec![range.clone()];
+                for c in cs {
+                    let mut next = Vec::new();
+                    for r in &invalid {
+                        let (v, i) = c.narrow(r);
+                        valid.extend(v);
+                        next.extend(i);
+                    }
+                    invalid = next;
+                }
+                (valid, invalid)
             }
         }
-        unreachable!();
     }
 
-    fn next_moves(&self, range: &PartRange) -> Vec<Move> {
-        let mut moves = Vec::new();
-        let mut range = range.clone();
-        for (condition, res) in self.rules() {
-            if let Some(condition) = condition {
-                if let Ok((valid_next, invalid_next)) = range.narrow(condition) {
-                    moves.push(Move {
-                        range: valid_next,
-                        next: res,
-                    });
-                    if let Some(invalid_next) = invalid_next {
-                        range = invalid_next;
-                    } else {
-                        break;
-                    }
-                }
-            } else {
-                moves.push(Move {
-                    range: range.clone(),
-                    next: res,
-                });
+    fn categories<'a>(&'a self, out: &mut BTreeSet<&'a str>) {
+        match self {
+            Condition::Test { category, .. } => {
+                out.insert(category);
             }
+            Condition::All(cs) | Condition::Any(cs) => cs.iter().for_each(|c| c.categories(out)),
         }
-        moves
     }
 }
 
-#[derive(Debug)]
-struct Part {
-    x: u64,
-    s: u64,
-    m: u64,
-    a: u64,
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Test { category, op, val } => {
+                write!(f, "{}{}{}", category, op.symbol(), val)
+            }
+            Condition::All(cs) => {
+                let cs: Vec<String> = cs
+                    .iter()
+                    .map(|c| match c {
+                        Condition::Any(_) => format!("({})", c),
+                        _ => c.to_string(),
+                    })
+                    .collect();
+                write!(f, "{}", cs.join("&&"))
+            }
+            Condition::Any(cs) => {
+                let cs: Vec<String> = cs.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", cs.join("||"))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-struct PartRange {
-    x: Range<u64>,
-    s: Range<u64>,
-    m: Range<u64>,
-    a: Range<u64>,
+struct Rule {
+    // None for the final rule, which always applies.
+    condition: Option<Condition>,
+    next: EvaResult,
 }
 
-impl PartRange {
-    fn narrow(&self, rule: &str) -> Result<(PartRange, Option<PartRange>), ()> {
-        let sep;
-        if rule.contains("<") {
-            sep = "<";
-        } else {
-            sep = ">";
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.condition {
+            Some(c) => write!(f, "{}:{}", c, self.next),
+            None => write!(f, "{}", self.next),
         }
+    }
+}
 
-        let [part, val] = rule.split(sep).collect::<Vec<&str>>().try_into().unwrap();
-        let val: u64 = val.parse().unwrap();
-        match part {
-            "x" => {
-                let (valid_range, invalid_range) = Self::narrow_condition(&self.x, sep, val)?;
-                let valid_range = PartRange {
-                    x: valid_range,
-                    ..self.clone()
-                };
-                let invalid_range = if let Some(invalid_range) = invalid_range {
-                    Some(PartRange {
-                        x: invalid_range,
-                        ..self.clone()
-                    })
-                } else {
-                    None
-                };
-                Ok((valid_range, invalid_range))
+// Calls nested deeper than this are taken to recurse forever.
+const MAX_DEPTH: usize = 64;
+
+type Stack = Vec<(String, usize)>;
+
+// Where the parts of a range end up when run through the workflows.
+#[derive(Debug, Default)]
+struct Propagation {
+    accepted: Vec<PartRange>,
+    // The workflows some part enters.
+    entered: HashSet<String>,
+    // The rules that apply to some part, as (workflow, rule).
+    fired: HashSet<(String, usize)>,
+    // Parts that never finish: the workflows they keep going through, and how many parts.
+    loops: Vec<(Vec<String>, u128)>,
+}
+
+#[derive(Debug)]
+struct Workflows(HashMap<String, Workflow>);
+
+impl Workflows {
+    // The part's rating sum if it is accepted. A part that comes back to the same workflow with
+    // the same calls pending would do so forever, and is reported as an error like a target that
+    // does not exist or a test of a rating the part lacks.
+    fn eva(&self, part: &Part) -> Result<Option<u64>, String> {
+        let get = |name: &str| {
+            self.0
+                .get_key_value(name)
+                .ok_or_else(|| format!("undefined workflow {}", name))
+        };
+        let mut stack: Vec<(&str, usize)> = Vec::new();
+        let mut seen: HashSet<(&str, Vec<(&str, usize)>)> = HashSet::new();
+        let (mut name, mut from) = (get("in")?.0.as_str(), 0);
+        loop {
+            if (from == 0 && !seen.insert((name, stack.clone()))) || stack.len() > MAX_DEPTH {
+                return Err(format!("loops forever through {}", name));
             }
-            "a" => {
-                let (valid_range, invalid_range) = Self::narrow_condition(&self.a, sep, val)?;
-                let valid_range = PartRange {
-                    a: valid_range,
-                    ..self.clone()
-                };
-                let invalid_range = if let Some(invalid_range) = invalid_range {
-                    Some(PartRange {
-                        a: invalid_range,
-                        ..self.clone()
-                    })
-                } else {
-                    None
-                };
-                Ok((valid_range, invalid_range))
+            // Resuming past the final rule returns straight away.
+            let (rule, res) = self.0[name]
+                .eva(part, from)
+                .map_err(|e| format!("{} for workflow {}", e, name))?
+                .unwrap_or((from, &EvaResult::Return));
+            match res {
+                EvaResult::Reject => return Ok(None),
+                EvaResult::Accept => return Ok(Some(part.sum())),
+                EvaResult::Move(next) => (name, from) = (get(next)?.0, 0),
+                EvaResult::Call(next) => {
+                    stack.push((name, rule + 1));
+                    (name, from) = (get(next)?.0, 0);
+                }
+                EvaResult::Return => match stack.pop() {
+                    Some(frame) => (name, from) = frame,
+                    None => return Ok(None),
+                },
             }
-            "s" => {
-                let (valid_range, invalid_range) = Self::narrow_condition(&self.s, sep, val)?;
-                let valid_range = PartRange {
-                    s: valid_range,
-                    ..self.clone()
-                };
-                let invalid_range = if let Some(invalid_range) = invalid_range {
-                    Some(PartRange {
-                        s: invalid_range,
-                        ..self.clone()
-                    })
-                } else {
-                    None
-                };
-                Ok((valid_range, invalid_range))
+        }
+    }
+
+    // Every category tested by some workflow.
+    fn categories(&self) -> BTreeSet<&str> {
+        let mut out = BTreeSet::new();
+        for workflow in self.0.values() {
+            for rule in &workflow.rules {
+                if let Some(c) = &rule.condition {
+                    c.categories(&mut out);
+                }
             }
-            "m" => {
-                let (valid_range, invalid_range) = Self::narrow_condition(&self.m, sep, val)?;
-                let valid_range = PartRange {
-                    m: valid_range,
-                    ..self.clone()
+        }
+        out
+    }
+
+    fn acceptable_ranges(&self, range: PartRange) -> Vec<PartRange> {
+        self.propagate(range).accepted
+    }
+
+    // Follows `range` from the start of `in` through every workflow. Each branch carries its own
+    // call stack of (workflow, rule to resume) and the (workflow, call stack) states it has
+    // entered, so that parts coming back to a state are caught instead of looping. Targets that do
+    // not exist are dropped.
+    fn propagate(&self, range: PartRange) -> Propagation {
+        let mut out = Propagation::default();
+        if !self.0.contains_key("in") {
+            return out;
+        }
+        out.entered.insert("in".to_string());
+        let start = ("in".to_string(), Stack::new());
+        let mut wl = vec![("in".to_string(), 0, range, Stack::new(), vec![start])];
+        while let Some((name, from, range, stack, path)) = wl.pop() {
+            let workflow = &self.0[name.as_str()];
+            for mov in workflow.next_moves(&range, from) {
+                if mov.rule < workflow.rules.len() {
+                    out.fired.insert((name.clone(), mov.rule));
+                }
+                let mut stack = stack.clone();
+                let next = match mov.next {
+                    EvaResult::Reject => continue,
+                    EvaResult::Accept => {
+                        out.accepted.push(mov.range);
+                        continue;
+                    }
+                    EvaResult::Move(next) => next,
+                    EvaResult::Call(next) => {
+                        stack.push((name.clone(), mov.rule + 1));
+                        next
+                    }
+                    EvaResult::Return => {
+                        if let Some((caller, resume)) = stack.pop() {
+                            wl.push((caller, resume, mov.range, stack, path.clone()));
+                        }
+                        continue;
+                    }
                 };
-                let invalid_range = if let Some(invalid_range) = invalid_range {
-                    Some(PartRange {
-                        m: invalid_range,
-                        ..self.clone()
-                    })
+                if !self.0.contains_key(&next) {
+                    continue;
+                }
+                let state = (next.clone(), stack);
+                // Runaway recursion is reported from the latest call of the same workflow.
+                let again = if state.1.len() > MAX_DEPTH {
+                    path.iter().rposition(|(w, _)| *w == next)
                 } else {
-                    None
+                    path.iter().position(|s| *s == state)
                 };
-                Ok((valid_range, invalid_range))
+                if let Some(i) = again {
+                    let names = path[i..].iter().map(|(w, _)| w.clone()).collect();
+                    out.loops.push((names, mov.range.sum()));
+                    continue;
+                }
+                out.entered.insert(next.clone());
+                let mut path = path.clone();
+                let stack = state.1.clone();
+                path.push(state);
+                wl.push((next, 0, mov.range, stack, path));
             }
-            _ => unreachable!(),
         }
+        out
     }
+}
 
-    fn narrow_condition(
-        range: &Range<u64>,
-        op: &str,
-        val: u64,
-    ) -> Result<(Range<u64>, Option<Range<u64>>), ()> {
-        match op {
-            "<" => {
-                if range.start >= val {
-                    Err(())
-                } else if range.contains(&val) {
-                    Ok((
-                        Range {
-                            start: range.start,
-                            end: val,
-                        },
-                        Some(Range {
-                            start: val,
-                            end: range.end,
-                        }),
-                    ))
-                } else {
-                    Ok((range.clone(), None))
-                }
+#[derive(Debug)]
+struct Workflow {
+    rules: Vec<Rule>,
+}
+
+impl Workflow {
+    // The first rule from `from` on that applies to the part, with its index.
+    fn eva(&self, part: &Part, from: usize) -> Result<Option<(usize, &EvaResult)>, String> {
+        for (i, rule) in self.rules.iter().enumerate().skip(from) {
+            if rule.condition.as_ref().map_or(Ok(true), |c| c.meet(part))? {
+                return Ok(Some((i, &rule.next)));
             }
-            ">" => {
-                if range.end <= val {
-                    Err(())
-                } else if range.contains(&val) {
-                    Ok((
-                        Range {
-                            start: val + 1,
-                            end: range.end,
-                        },
-                        Some(Range {
-                            start: range.start,
-                            end: val + 1,
-                        }),
-                    ))
-                } else {
-                    Ok((range.clone(), None))
-                }
+        }
+        Ok(None)
+    }
+
+    // Splits `range` by the rules from `from` on, returning which parts of it each rule sends where.
+    // Resuming past the final rule returns straight away.
+    fn next_moves(&self, range: &PartRange, from: usize) -> Vec<Move> {
+        if from >= self.rules.len() {
+            return vec![Move {
+                range: range.clone(),
+                rule: from,
+                next: EvaResult::Return,
+            }];
+        }
+        let mut moves = Vec::new();
+        let mut ranges = vec![range.clone()];
+        for (i, rule) in self.rules.iter().enumerate().skip(from) {
+            let mut rest = Vec::new();
+            for range in ranges {
+                let valid = match &rule.condition {
+                    Some(condition) => {
+                        let (valid, invalid) = condition.narrow(&range);
+                        rest.extend(invalid);
+                        valid
+                    }
+                    None => vec![range],
+                };
+                moves.extend(valid.into_iter().map(|range| Move {
+                    range,
+                    rule: i,
+                    next: rule.next.clone(),
+                }));
+            }
+            ranges = rest;
+            if ranges.is_empty() {
+                break;
             }
-            _ => unreachable!(),
         }
+        moves
+    }
+}
+
+// A part's ratings by category.
+#[derive(Debug)]
+struct Part(BTreeMap<String, u64>);
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ratings: Vec<String> = self.0.iter().map(|(c, v)| format!("{}={}", c, v)).collect();
+        write!(f, "{{{}}}", ratings.join(","))
+    }
+}
+
+impl Part {
+    fn get(&self, category: &str) -> Option<u64> {
+        self.0.get(category).copied()
     }
 
     fn sum(&self) -> u64 {
-        (self.x.end - self.x.start)
-            * (self.a.end - self.a.start)
-            * (self.s.end - self.s.start)
-            * (self.m.end - self.m.start)
+        self.0.values().sum()
+    }
+}
+
+// The parts whose rating in every category lies in that category's range.
+#[derive(Debug, Clone)]
+struct PartRange(BTreeMap<String, Range<u64>>);
+
+impl PartRange {
+    fn new<'a>(categories: impl Iterator<Item = &'a str>, range: Range<u64>) -> Self {
+        PartRange(categories.map(|c| (c.to_string(), range.clone())).collect())
+    }
+
+    fn with(&self, category: &str, range: Range<u64>) -> Self {
+        let mut out = self.clone();
+        out.0.insert(category.to_string(), range);
+        out
+    }
+
+    // How many parts the range holds, or None if that does not fit in a u128. Every category adds
+    // a factor of up to 4000, so this runs out at 11 categories.
+    fn checked_sum(&self) -> Option<u128> {
+        self.0
+            .values()
+            .try_fold(1u128, |n, r| n.checked_mul((r.end - r.start) as u128))
+    }
+
+    fn sum(&self) -> u128 {
+        self.checked_sum()
+            .expect("part count does not fit in a u128")
     }
 }
 
 #[derive(Debug)]
 struct Move {
     range: PartRange,
+    // The rule that sends the range on.
+    rule: usize,
     next: EvaResult,
 }
 
-impl Part {
-    fn meet(&self, rule: &str) -> bool {
-        let sep;
-        if rule.contains("<") {
-            sep = "<";
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(u64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 15] = [
+    "<=", ">=", "==", "!=", "&&", "||", "<", ">", "{", "}", ",", ":", "(", ")", "@",
+];
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+        } else if c.is_ascii_alphabetic() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            tokens.push((pos, Token::Name(rest[..len].to_string())));
+            pos += len;
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let number = rest[..len]
+                .parse()
+                .map_err(|e| format!("bad number at {}: {}", pos, e))?;
+            tokens.push((pos, Token::Number(number)));
+            pos += len;
+        } else if c == '^' {
+            tokens.push((pos, Token::Symbol("^")));
+            pos += 1;
+        } else if let Some(s) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push((pos, Token::Symbol(s)));
+            pos += s.len();
         } else {
-            sep = ">";
+            return Err(format!("unexpected character {:?} at {}", c, pos));
         }
+    }
+    Ok(tokens)
+}
 
-        let [part, val] = rule.split(sep).collect::<Vec<&str>>().try_into().unwrap();
-        let val: u64 = val.parse().unwrap();
-        match part {
-            "x" => Self::meet_condition(self.x, sep, val),
-            "s" => Self::meet_condition(self.s, sep, val),
-            "m" => Self::meet_condition(self.m, sep, val),
-            "a" => Self::meet_condition(self.a, sep, val),
-            _ => unreachable!(),
+// A recursive descent parser over the tokens of one workflow line.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.peek().cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn unexpected(&self) -> String {
+        match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some((pos, t)) if self.pos <= self.tokens.len() => {
+                format!("unexpected {:?} at {}", t, pos)
+            }
+            _ => "unexpected end of input".to_string(),
         }
     }
 
-    fn meet_condition(left: u64, op: &str, right: u64) -> bool {
-        match op {
-            "<" => left < right,
-            ">" => left > right,
-            _ => unreachable!(),
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            _ => Err(self.unexpected()),
         }
     }
 
-    fn sum(&self) -> u64 {
-        self.x + self.m + self.a + self.s
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Name(name)) => Ok(name),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn workflow(&mut self) -> Result<(String, Workflow), String> {
+        let name = self.name()?;
+        self.expect("{")?;
+        let mut rules = Vec::new();
+        loop {
+            // A rule is conditional when a `:` follows before the next `,` or `}`.
+            let conditional = self.tokens[self.pos..]
+                .iter()
+                .map(|(_, t)| t)
+                .take_while(|t| **t != Token::Symbol(",") && **t != Token::Symbol("}"))
+                .any(|t| *t == Token::Symbol(":"));
+            let condition = if conditional {
+                let condition = self.any()?;
+                self.expect(":")?;
+                Some(condition)
+            } else {
+                None
+            };
+            let next = self.target()?;
+            rules.push(Rule { condition, next });
+            match self.next() {
+                Some(Token::Symbol(",")) => {}
+                Some(Token::Symbol("}")) => break,
+                _ => return Err(self.unexpected()),
+            }
+        }
+        if rules.last().unwrap().condition.is_some() {
+            return Err(format!(
+                "workflow {} must end with a rule without a condition",
+                name
+            ));
+        }
+        if self.peek().is_some() {
+            self.next();
+            return Err(self.unexpected());
+        }
+        Ok((name, Workflow { rules }))
+    }
+
+    fn any(&mut self) -> Result<Condition, String> {
+        let mut cs = vec![self.all()?];
+        while self.peek() == Some(&Token::Symbol("||")) {
+            self.next();
+            cs.push(self.all()?);
+        }
+        Ok(if cs.len() == 1 {
+            cs.pop().unwrap()
+        } else {
+            Condition::Any(cs)
+        })
+    }
+
+    fn all(&mut self) -> Result<Condition, String> {
+        let mut cs = vec![self.test()?];
+        while self.peek() == Some(&Token::Symbol("&&")) {
+            self.next();
+            cs.push(self.test()?);
+        }
+        Ok(if cs.len() == 1 {
+            cs.pop().unwrap()
+        } else {
+            Condition::All(cs)
+        })
+    }
+
+    fn test(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some(&Token::Symbol("(")) {
+            self.next();
+            let c = self.any()?;
+            self.expect(")")?;
+            return Ok(c);
+        }
+        let category = self.name()?;
+        let op = match self.next() {
+            Some(Token::Symbol("<")) => Op::Lt,
+            Some(Token::Symbol(">")) => Op::Gt,
+            Some(Token::Symbol("<=")) => Op::Le,
+            Some(Token::Symbol(">=")) => Op::Ge,
+            Some(Token::Symbol("==")) => Op::Eq,
+            Some(Token::Symbol("!=")) => Op::Ne,
+            _ => return Err(self.unexpected()),
+        };
+        let val = match self.next() {
+            // Ranges end one past their last value, which has to fit in a u64.
+            Some(Token::Number(n)) if n == u64::MAX => {
+                return Err(format!("{} is too large to test against", n))
+            }
+            Some(Token::Number(n)) => n,
+            _ => return Err(self.unexpected()),
+        };
+        Ok(Condition::Test { category, op, val })
+    }
+
+    fn target(&mut self) -> Result<EvaResult, String> {
+        Ok(match self.next() {
+            Some(Token::Name(name)) if name == "A" => EvaResult::Accept,
+            Some(Token::Name(name)) if name == "R" => EvaResult::Reject,
+            Some(Token::Name(name)) => EvaResult::Move(name),
+            Some(Token::Symbol("@")) => EvaResult::Call(self.name()?),
+            Some(Token::Symbol("^")) => EvaResult::Return,
+            _ => return Err(self.unexpected()),
+        })
     }
 }
 
-fn parse(content: &str) -> (Workflows, Vec<Part>) {
+fn parse(content: &str) -> Result<(Workflows, Vec<Part>), String> {
     let mut is_part = false;
     let mut parts = Vec::new();
     let mut workflows = HashMap::new();
-    content.lines().for_each(|line| {
+    for (n, line) in content.lines().enumerate() {
         if line.is_empty() {
             is_part = true;
-            return;
+            continue;
         }
         if !is_part {
-            let [name, line] = line.split("{").collect::<Vec<&str>>().try_into().unwrap();
-            let rule = line.strip_suffix("}").unwrap().to_string();
-            workflows.insert(name, Workflow { rule });
+            let mut parser = Parser {
+                tokens: tokenize(line).map_err(|e| format!("line {}: {}", n + 1, e))?,
+                pos: 0,
+            };
+            let (name, workflow) = parser
+                .workflow()
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+            workflows.insert(name, workflow);
         } else {
             let line = line.strip_prefix("{").unwrap().strip_suffix("}").unwrap();
-            let m: HashMap<&str, u64> = HashMap::from_iter(
+            let m: BTreeMap<String, u64> = BTreeMap::from_iter(
                 line.split(",")
                     .map(|category| category.split("=").collect::<Vec<&str>>())
-                    .map(|v| (v[0], v[1].parse().unwrap())),
+                    .map(|v| (v[0].to_string(), v[1].parse().unwrap())),
             );
-            parts.push(Part {
-                x: m["x"],
-                s: m["s"],
-                m: m["m"],
-                a: m["a"],
-            });
+            parts.push(Part(m));
         }
-    });
-    (Workflows(workflows), parts)
+    }
+    Ok((Workflows(workflows), parts))
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("./exe <file>");
+    if args.len() < 2 {
+        panic!("./exe <file> [--analyze] [--simplify] [--dot <file>]");
     }
     let content = read_to_string(&args[1]).unwrap();
-    let (workflows, parts) = parse(&content);
+    let flags = &args[2..];
+    let (workflows, parts) = match parse(&content) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
 
-    let part1 = parts.iter().fold(0, |acc, part| {
-        if let Some(v) = workflows.eva(part) {
-            acc + v
-        } else {
-            acc
+    let mut part1 = 0;
+    for part in &parts {
+        match workflows.eva(part) {
+            Ok(v) => part1 += v.unwrap_or(0),
+            Err(e) => println!("part {}: {}", part, e),
         }
-    });
+    }
     println!("{}", part1);
 
+    // Every category rated by a part or tested by a workflow ranges over 1..=4000.
+    let mut categories = workflows.categories();
+    parts
+        .iter()
+        .for_each(|part| categories.extend(part.0.keys().map(|c| c.as_str())));
+    let universe = PartRange::new(categories.into_iter(), 1..4001);
+    // Every range the workflows split off holds fewer parts than the universe.
+    if universe.checked_sum().is_none() {
+        println!(
+            "{} categories give too many combinations to count",
+            universe.0.len()
+        );
+        exit(1);
+    }
     let mut sum = 0;
-    for range in workflows.acceptable_ranges() {
+    for range in workflows.acceptable_ranges(universe.clone()) {
         sum += range.sum();
     }
     println!("{}", sum);
+
+    let analysis = analysis::analyze(&workflows, universe);
+    if flags.iter().any(|a| a == "--analyze") {
+        print!("{}", analysis.describe());
+    }
+    if flags.iter().any(|a| a == "--simplify") {
+        print!("{}", analysis::simplify(&workflows, &analysis));
+    }
+    if let Some(i) = flags.iter().position(|a| a == "--dot") {
+        write(&flags[i + 1], analysis::dot(&workflows, &analysis)).unwrap();
+    }
 }